@@ -1,4 +1,4 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "Database Migration Tool (dmt)")]
@@ -10,9 +10,28 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: DmtCommand,
 
-    /// Sets a custom config file
-    #[arg(short, long, value_name = "FILE", default_value = "./dmt.config.yml")]
-    pub config: String,
+    /// Sets a custom config file. Defaults to the first dmt.config.{toml,yml,yaml,json} found in
+    /// the current directory
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// The config profile to use, e.g. staging. Defaults to the DMT_ENV environment variable
+    #[arg(short, long, value_name = "PROFILE")]
+    pub env: Option<String>,
+
+    /// How to print progress and results
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Human)]
+    pub format: OutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain text for people to read
+    Human,
+    /// One JSON object per line
+    Json,
+    /// Print nothing but errors
+    Quiet,
 }
 
 #[derive(Subcommand)]
@@ -20,13 +39,82 @@ pub enum DmtCommand {
     /// Creates a new migration
     New(NewMigrationArgs),
     /// Execute all outstanding migrations
-    Migrate,
+    Migrate(MigrateArgs),
     /// Rollback to before last migration
-    Rollback,
+    Rollback(RollbackArgs),
+    /// List applied, pending and missing migrations
+    Status(StatusArgs),
+    /// Check that applied migrations have not been changed since they were run
+    Verify,
+    /// Release the migration lock, ending the Postgres or MySQL session that holds it
+    ///
+    /// Only use this when the migration that took the lock did not finish. On Postgres and MySQL
+    /// the lock belongs to the database session that took it, and the only way to release it is
+    /// to end that session, which interrupts its migration if it is in fact still running.
+    Unlock,
+    /// Wait until the database accepts connections, retrying as set in [connection.retry]
+    Wait(WaitArgs),
 }
 
 #[derive(Args)]
 pub struct NewMigrationArgs {
     /// The name of the new migration
     pub name: String,
+
+    /// Create a single .sql file with -- dmt:up and -- dmt:down sections instead of a directory
+    #[arg(long)]
+    pub single_file: bool,
+}
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    /// Only migrate up to and including this version
+    #[arg(long, value_name = "VERSION")]
+    pub to: Option<String>,
+
+    #[command(flatten)]
+    pub dry_run: DryRunArgs,
+}
+
+#[derive(Args)]
+pub struct RollbackArgs {
+    /// The number of migrations to roll back
+    #[arg(long, default_value_t = 1, conflicts_with_all = ["all", "to"])]
+    pub steps: usize,
+
+    /// Roll back every applied migration
+    #[arg(long, conflicts_with = "to")]
+    pub all: bool,
+
+    /// Roll back every migration applied after this version
+    #[arg(long, value_name = "VERSION")]
+    pub to: Option<String>,
+
+    #[command(flatten)]
+    pub dry_run: DryRunArgs,
+}
+
+#[derive(Args)]
+pub struct DryRunArgs {
+    /// Print the SQL that would be run, in order, without changing the database
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Write the SQL of a dry run to this file as a single script instead of printing it
+    #[arg(long, value_name = "FILE", requires = "dry_run")]
+    pub output: Option<String>,
+}
+
+#[derive(Args)]
+pub struct WaitArgs {
+    /// Keep retrying for up to this many seconds, however many attempts the config allows
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    /// Exit with a nonzero code if any migrations are pending
+    #[arg(long)]
+    pub fail_on_pending: bool,
 }
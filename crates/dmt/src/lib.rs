@@ -1,32 +1,271 @@
-use self::cli::{Cli, DmtCommand};
+use std::fs;
+use std::process::ExitCode;
+
+use self::cli::{Cli, DmtCommand, DryRunArgs, OutputFormat, StatusArgs};
+use chrono::NaiveDateTime;
 use clap::Parser;
-use libdmt::{DatabaseConnection, DmtConfig, DmtError, MigrationDatabase};
+use libdmt::{
+    DatabaseConnection, DmtConfig, DmtError, HumanReporter, JsonReporter, MigrationDatabase,
+    MigrationError, MigrationState, Migrator, PlannedMigration, QuietReporter, Reporter,
+    RetryConfig, RollbackTarget,
+};
+use serde_json::json;
 
 mod cli;
 
-pub fn run_dmt() -> Result<(), DmtError> {
+pub fn run_dmt() -> Result<ExitCode, DmtError> {
     let cli = Cli::parse();
-    let config = DmtConfig::from_file(&cli.config).map_err(DmtError::ConfigError)?;
+    let mut config = match &cli.config {
+        Some(path) => DmtConfig::from_file_with_profile(path, cli.env.as_deref()),
+        None => DmtConfig::discover_with_profile(".", cli.env.as_deref()),
+    }
+    .map_err(DmtError::ConfigError)?;
+
+    // Waiting always retries, with the default settings if the config has none.
+    if let DmtCommand::Wait(opts) = &cli.command {
+        let retry = config
+            .connection
+            .retry
+            .get_or_insert_with(RetryConfig::default);
 
-    let mut db = MigrationDatabase::try_from(&config)?;
+        if let Some(timeout) = opts.timeout {
+            retry.max_attempts = u32::MAX;
+            retry.deadline = Some(timeout);
+        }
+    }
 
-    handle_command(&cli.command, &mut db, &config).unwrap();
+    let mut db = MigrationDatabase::connect_with_reporter(&config, reporter(cli.format))?;
 
-    Ok(())
+    handle_command(&cli.command, &mut db, &config, cli.format)
 }
 
 fn handle_command(
     command: &DmtCommand,
     db: &mut impl DatabaseConnection,
     config: &DmtConfig,
-) -> Result<(), DmtError> {
+    format: OutputFormat,
+) -> Result<ExitCode, DmtError> {
     match command {
         DmtCommand::New(opts) => {
-            libdmt::new_migration(&opts.name, &config.migration.migration_path)?
+            if opts.single_file || config.migration.single_file {
+                libdmt::new_single_file_migration(&opts.name, &config.migration.migration_path)?;
+            } else {
+                libdmt::new_migration(&opts.name, &config.migration.migration_path)?;
+            }
+            print_message(format, "created", "Created the new migration.");
+        }
+        DmtCommand::Migrate(opts) => {
+            let migrator = migrator(config, format);
+
+            if opts.dry_run.dry_run {
+                let plan = match &opts.to {
+                    Some(target) => migrator.plan_to(db, target)?,
+                    None => migrator.plan(db)?,
+                };
+
+                return dry_run(&plan, &opts.dry_run, format);
+            }
+
+            match &opts.to {
+                Some(target) => migrator.run_to(db, target)?,
+                None => migrator.run(db)?,
+            };
+        }
+        DmtCommand::Rollback(opts) => {
+            let target = if let Some(version) = &opts.to {
+                RollbackTarget::To(version.clone())
+            } else if opts.all {
+                RollbackTarget::All
+            } else {
+                RollbackTarget::Steps(opts.steps)
+            };
+
+            let migrator = migrator(config, format);
+
+            if opts.dry_run.dry_run {
+                let plan = migrator.plan_rollback(db, target)?;
+                return dry_run(&plan, &opts.dry_run, format);
+            }
+
+            migrator.rollback(db, target)?;
+        }
+        DmtCommand::Status(opts) => return status(db, config, opts, format),
+        DmtCommand::Verify => {
+            libdmt::verify_migrations(db, &config.migration.migration_path)?;
+            print_message(
+                format,
+                "verified",
+                "All applied migrations match their files.",
+            );
+        }
+        DmtCommand::Unlock => {
+            db.force_unlock().map_err(MigrationError::from)?;
+            print_message(format, "unlocked", "Released the migration lock.");
+        }
+        DmtCommand::Wait(_) => {
+            print_message(format, "ready", "The database is accepting connections.");
         }
-        DmtCommand::Migrate => libdmt::run_migrations(db, &config.migration.migration_path)?,
-        DmtCommand::Rollback => libdmt::rollback_migrations(db, &config.migration.migration_path)?,
     };
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+fn reporter(format: OutputFormat) -> &'static dyn Reporter {
+    match format {
+        OutputFormat::Human => &HumanReporter,
+        OutputFormat::Json => &JsonReporter,
+        OutputFormat::Quiet => &QuietReporter,
+    }
+}
+
+fn migrator(config: &DmtConfig, format: OutputFormat) -> Migrator<Box<dyn Reporter>> {
+    let reporter: Box<dyn Reporter> = match format {
+        OutputFormat::Human => Box::new(HumanReporter),
+        OutputFormat::Json => Box::new(JsonReporter),
+        OutputFormat::Quiet => Box::new(QuietReporter),
+    };
+
+    Migrator::from_config(&config.migration).reporter(reporter)
+}
+
+/// Prints the SQL of `plan`, or writes it to the file given with `--output`.
+fn dry_run(
+    plan: &[PlannedMigration],
+    opts: &DryRunArgs,
+    format: OutputFormat,
+) -> Result<ExitCode, DmtError> {
+    let script: String = plan
+        .iter()
+        .map(|migration| {
+            let Some(sql) = &migration.sql else {
+                return format!(
+                    "-- {} ({}, Rust migration)\n\n",
+                    migration.name,
+                    migration.direction.as_str()
+                );
+            };
+
+            let atomic = if migration.transactional {
+                ""
+            } else {
+                " (runs outside a transaction)"
+            };
+
+            format!(
+                "-- {}/{}.sql{atomic}\n{}\n\n",
+                migration.name,
+                migration.direction.as_str(),
+                sql.trim()
+            )
+        })
+        .collect();
+
+    if let Some(path) = &opts.output {
+        fs::write(path, script).map_err(MigrationError::from)?;
+        print_message(
+            format,
+            "planned",
+            &format!("Wrote the SQL of {} migration(s) to {path}.", plan.len()),
+        );
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    match format {
+        OutputFormat::Human if plan.is_empty() => println!("   Nothing to run."),
+        OutputFormat::Human => print!("{script}"),
+        OutputFormat::Json => {
+            for migration in plan {
+                println!(
+                    "{}",
+                    json!({
+                        "event": "planned",
+                        "name": migration.name,
+                        "direction": migration.direction.as_str(),
+                        "sql": migration.sql,
+                        "transactional": migration.transactional,
+                    })
+                );
+            }
+        }
+        OutputFormat::Quiet => {}
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the outcome of a command that has no output of its own.
+fn print_message(format: OutputFormat, event: &str, message: &str) {
+    match format {
+        OutputFormat::Human => println!("   {message}"),
+        OutputFormat::Json => println!("{}", json!({ "event": event })),
+        OutputFormat::Quiet => {}
+    }
+}
+
+fn status(
+    db: &mut impl DatabaseConnection,
+    config: &DmtConfig,
+    opts: &StatusArgs,
+    format: OutputFormat,
+) -> Result<ExitCode, DmtError> {
+    let statuses = libdmt::migration_status(db, &config.migration.migration_path)?;
+
+    if statuses.is_empty() && format == OutputFormat::Human {
+        println!("   No migrations found.");
+    }
+
+    let mut pending = 0;
+    for status in &statuses {
+        let (state, time) = match status.state {
+            MigrationState::Applied(time) => ("applied", Some(time)),
+            MigrationState::Pending => {
+                pending += 1;
+                ("pending", None)
+            }
+            MigrationState::Missing(time) => ("missing", Some(time)),
+        };
+
+        match format {
+            OutputFormat::Human => {
+                let time = time
+                    .map(|time| format!(" ({})", format_time(time)))
+                    .unwrap_or_default();
+                let atomic = if status.transactional {
+                    ""
+                } else {
+                    " [non-atomic]"
+                };
+
+                println!(
+                    "    {}: {}{time}{atomic}",
+                    state.to_uppercase(),
+                    status.name
+                );
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "name": status.name,
+                    "state": state,
+                    "time": time.map(format_time),
+                    "transactional": status.transactional,
+                })
+            ),
+            OutputFormat::Quiet => {}
+        }
+    }
+
+    if opts.fail_on_pending && pending > 0 {
+        if format != OutputFormat::Quiet {
+            eprintln!("{pending} migration(s) pending");
+        }
+        return Ok(ExitCode::FAILURE);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn format_time(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
 }
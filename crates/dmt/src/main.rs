@@ -1,5 +1,8 @@
-use libdmt::DmtError;
+use std::process::{ExitCode, Termination};
 
-fn main() -> Result<(), DmtError> {
-    dmt::run_dmt()
+fn main() -> ExitCode {
+    match dmt::run_dmt() {
+        Ok(code) => code,
+        Err(err) => err.report(),
+    }
 }
@@ -0,0 +1,48 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::io;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    FileError(io::Error),
+    UnrecognizedConfigFormat(String),
+    ParseError(String),
+    UnresolvedVariable(String),
+    UnknownProfile(String),
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        Self::FileError(err)
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::FileError(error) => f.write_str(error.to_string().as_str()),
+            ConfigError::UnrecognizedConfigFormat(msg) => f.write_str(msg),
+            ConfigError::ParseError(msg) => f.write_str(msg),
+            ConfigError::UnresolvedVariable(name) => write!(
+                f,
+                "The config refers to `${{{name}}}`, but `{name}` is not set in the environment, \
+                 the env file or env.vars, and has no default"
+            ),
+            ConfigError::UnknownProfile(name) => {
+                write!(f, "No profile named `{name}` is defined in the config")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::FileError(error) => error.source(),
+            ConfigError::UnrecognizedConfigFormat(_) => None,
+            ConfigError::ParseError(_) => None,
+            ConfigError::UnresolvedVariable(_) => None,
+            ConfigError::UnknownProfile(_) => None,
+        }
+    }
+}
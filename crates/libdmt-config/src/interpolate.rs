@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::ConfigError;
+
+use super::EnvConfig;
+
+/// The values `${VAR}` references in the config are resolved against.
+///
+/// A variable is looked up in, from highest to lowest precedence:
+///
+/// 1. `environment`, which is the environment of the running process outside of tests,
+/// 2. the `.env` file named by `env.file`,
+/// 3. the `env.vars` table of the config,
+///
+/// and finally falls back to the default given with `${VAR:-default}`.
+pub(crate) struct Variables<'a> {
+    environment: &'a dyn Fn(&str) -> Option<String>,
+    file: HashMap<String, String>,
+    vars: HashMap<String, String>,
+}
+
+impl<'a> Variables<'a> {
+    pub(crate) fn new(
+        config: Option<&EnvConfig>,
+        environment: &'a dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let Some(config) = config else {
+            return Ok(Self {
+                environment,
+                file: HashMap::new(),
+                vars: HashMap::new(),
+            });
+        };
+
+        let file = match &config.file {
+            Some(path) => parse_env_file(&fs::read_to_string(path)?),
+            None => HashMap::new(),
+        };
+
+        Ok(Self {
+            environment,
+            file,
+            vars: config.vars.clone().unwrap_or_default(),
+        })
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        (self.environment)(name)
+            .or_else(|| self.file.get(name).cloned())
+            .or_else(|| self.vars.get(name).cloned())
+    }
+
+    /// Replaces every `${VAR}` and `${VAR:-default}` in `value`, the value of the setting `key`.
+    /// `$${` is left as a literal `${`.
+    pub(crate) fn interpolate(&self, key: &str, value: &mut String) -> Result<(), ConfigError> {
+        if !value.contains("${") {
+            return Ok(());
+        }
+
+        let mut result = String::with_capacity(value.len());
+        let mut rest = value.as_str();
+
+        while let Some(start) = rest.find('$') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if let Some(escaped) = rest.strip_prefix("$${") {
+                result.push_str("${");
+                rest = escaped;
+            } else if let Some(reference) = rest.strip_prefix("${") {
+                let end = reference.find('}').ok_or_else(|| {
+                    // The value itself is left out, as it may be a password.
+                    ConfigError::ParseError(format!("Unterminated `${{` in the value of `{key}`"))
+                })?;
+
+                let (name, default) = match reference[..end].split_once(":-") {
+                    Some((name, default)) => (name, Some(default)),
+                    None => (&reference[..end], None),
+                };
+
+                // As in the shell, an empty value also falls back to the default.
+                let resolved = self
+                    .get(name)
+                    .filter(|value| default.is_none() || !value.is_empty())
+                    .or_else(|| default.map(str::to_string))
+                    .ok_or_else(|| ConfigError::UnresolvedVariable(name.to_string()))?;
+
+                result.push_str(&resolved);
+                rest = &reference[end + 1..];
+            } else {
+                result.push('$');
+                rest = &rest[1..];
+            }
+        }
+
+        result.push_str(rest);
+        *value = result;
+
+        Ok(())
+    }
+}
+
+/// Parses the `KEY=value` lines of a `.env` file. Blank lines, `#` comments and a leading
+/// `export` are ignored, and values may be wrapped in single or double quotes.
+fn parse_env_file(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|quote| {
+                    value
+                        .strip_prefix(*quote)
+                        .and_then(|value| value.strip_suffix(*quote))
+                })
+                .unwrap_or(value);
+
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,934 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+mod error;
+mod interpolate;
+
+use serde::Deserialize;
+
+pub use error::ConfigError;
+use interpolate::Variables;
+
+/// Where the values of `${VAR}` references in the config come from, besides the process
+/// environment. See [`DmtConfig::parse`] for the order they are looked up in.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EnvConfig {
+    /// A `.env` file of `KEY=value` lines, relative to the working directory.
+    pub file: Option<String>,
+    pub vars: Option<HashMap<String, String>>,
+}
+
+/// Seconds to wait for another process to release the migration lock.
+pub const DEFAULT_LOCK_TIMEOUT: u64 = 60;
+
+/// The table the applied migrations are recorded in.
+pub const DEFAULT_TABLE: &str = "migration";
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MigrationConfig {
+    #[serde(default = "default_migration_path")]
+    pub migration_path: String,
+    /// Seconds to wait for another process to release the migration lock.
+    #[serde(default = "default_lock_timeout")]
+    pub lock_timeout: u64,
+    /// Whether `dmt new` creates single `.sql` files with `-- dmt:up` and `-- dmt:down` sections
+    /// instead of directories holding an `up.sql` and a `down.sql`.
+    #[serde(default)]
+    pub single_file: bool,
+    /// The name of the table the applied migrations are recorded in.
+    #[serde(default = "default_table")]
+    pub table: String,
+    /// The schema of the migration table, which is created if it does not exist. Defaults to the
+    /// current schema on Postgres and the current database on MySQL. On SQLite it is the name of
+    /// an attached database.
+    pub schema: Option<String>,
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        default_migration_config()
+    }
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionConfig {
+    pub database: Option<Database>,
+    pub turso: Option<TursoConfig>,
+    pub postgres: Option<PostgresConfig>,
+    pub sqlite: Option<SqliteConfig>,
+    pub mysql: Option<MysqlConfig>,
+    /// How to retry connecting while the database is not yet accepting connections. Without it,
+    /// connecting is tried once.
+    pub retry: Option<RetryConfig>,
+}
+
+impl ConnectionConfig {
+    /// Replaces every setting that is given in `overrides`.
+    fn merge(&mut self, overrides: ConnectionConfig) {
+        self.database = overrides.database.or(self.database.take());
+        self.turso = overrides.turso.or(self.turso.take());
+        self.postgres = overrides.postgres.or(self.postgres.take());
+        self.sqlite = overrides.sqlite.or(self.sqlite.take());
+        self.mysql = overrides.mysql.or(self.mysql.take());
+        self.retry = overrides.retry.or(self.retry.take());
+    }
+
+    /// The string values that may contain `${VAR}` references, with their keys.
+    fn string_values_mut(&mut self) -> Vec<(&'static str, &mut String)> {
+        let mut values = Vec::new();
+
+        if let Some(turso) = &mut self.turso {
+            values.extend([
+                ("connection.turso.url", &mut turso.url),
+                ("connection.turso.token", &mut turso.token),
+            ]);
+        }
+        if let Some(postgres) = &mut self.postgres {
+            values.push((
+                "connection.postgres.connectionString",
+                &mut postgres.connection_string,
+            ));
+            values.extend(
+                [
+                    ("connection.postgres.host", &mut postgres.host),
+                    ("connection.postgres.dbname", &mut postgres.dbname),
+                    ("connection.postgres.user", &mut postgres.user),
+                    ("connection.postgres.password", &mut postgres.password),
+                    (
+                        "connection.postgres.passwordFile",
+                        &mut postgres.password_file,
+                    ),
+                    (
+                        "connection.postgres.applicationName",
+                        &mut postgres.application_name,
+                    ),
+                    ("connection.postgres.options", &mut postgres.options),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, value.as_mut()?))),
+            );
+
+            if let Some(tls) = &mut postgres.tls {
+                values.extend(
+                    [
+                        ("connection.postgres.tls.caFile", &mut tls.ca_file),
+                        ("connection.postgres.tls.clientCert", &mut tls.client_cert),
+                        ("connection.postgres.tls.clientKey", &mut tls.client_key),
+                    ]
+                    .into_iter()
+                    .filter_map(|(key, value)| Some((key, value.as_mut()?))),
+                );
+            }
+        }
+        if let Some(sqlite) = &mut self.sqlite {
+            values.push(("connection.sqlite.path", &mut sqlite.path));
+        }
+        if let Some(mysql) = &mut self.mysql {
+            values.push((
+                "connection.mysql.connectionString",
+                &mut mysql.connection_string,
+            ));
+        }
+
+        values
+    }
+}
+
+/// A named set of settings that replace those of the base config when the profile is selected.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub migration: Option<MigrationOverrides>,
+    pub connection: Option<ConnectionConfig>,
+}
+
+impl ProfileConfig {
+    /// The values of the profile that may contain `${VAR}` references.
+    fn string_values_mut(&mut self) -> Vec<(&'static str, &mut String)> {
+        let mut values = Vec::new();
+
+        if let Some(path) = self
+            .migration
+            .as_mut()
+            .and_then(|migration| migration.migration_path.as_mut())
+        {
+            values.push(("migration.migrationPath", path));
+        }
+        if let Some(connection) = &mut self.connection {
+            values.extend(connection.string_values_mut());
+        }
+
+        values
+    }
+}
+
+/// The [`MigrationConfig`] settings of a profile, each of which is optional.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MigrationOverrides {
+    pub migration_path: Option<String>,
+    pub lock_timeout: Option<u64>,
+    pub single_file: Option<bool>,
+    pub table: Option<String>,
+    pub schema: Option<String>,
+}
+
+/// The settings of a Postgres connection, given as a connection string, as separate keys, or
+/// both. The separate keys replace the settings of the connection string, except for `host` and
+/// `port`, which may only be given in one of them.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PostgresConfig {
+    #[serde(default)]
+    pub connection_string: String,
+    /// A host name, an IP address, or the directory of a Unix socket.
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub dbname: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// A file holding the password, such as a mounted secret. Cannot be used with `password`.
+    pub password_file: Option<String>,
+    pub application_name: Option<String>,
+    /// Seconds to wait for the connection to be made, where 0 waits indefinitely.
+    pub connect_timeout: Option<u64>,
+    /// Command line options for the server, such as `-c search_path=app`.
+    pub options: Option<String>,
+    pub tls: Option<PostgresTlsConfig>,
+}
+
+/// How a Postgres connection uses TLS, with the certificates to use for it. Any mode but
+/// `disable` and `prefer` needs the `native-tls` feature.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PostgresTlsConfig {
+    /// Replaces the `sslmode` of the connection string.
+    pub mode: Option<TlsMode>,
+    /// A PEM file of the certificates to trust besides the system's, such as the certificate of
+    /// a self-signed server.
+    pub ca_file: Option<String>,
+    /// A PEM file of the certificate to authenticate with, which needs `client_key` too.
+    pub client_cert: Option<String>,
+    /// A PEM file of the PKCS #8 private key of `client_cert`.
+    pub client_key: Option<String>,
+}
+
+/// The values of the `sslmode` setting of libpq, which mean the same here.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TlsMode {
+    /// Never use TLS.
+    Disable,
+    /// Use TLS if the server supports it, without verifying its certificate.
+    #[default]
+    Prefer,
+    /// Always use TLS. The certificate of the server is only verified if a `ca_file` is given.
+    Require,
+    /// Always use TLS, and check that the certificate of the server is signed by a trusted
+    /// authority.
+    VerifyCa,
+    /// Like `verify-ca`, and also check that the certificate was issued for the host connected to.
+    VerifyFull,
+}
+
+impl FromStr for TlsMode {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disable" => Ok(Self::Disable),
+            "prefer" => Ok(Self::Prefer),
+            "require" => Ok(Self::Require),
+            "verify-ca" => Ok(Self::VerifyCa),
+            "verify-full" => Ok(Self::VerifyFull),
+            _ => Err(ConfigError::ParseError(format!(
+                "Invalid sslmode `{s}`. Expected one of disable, prefer, require, verify-ca or \
+                 verify-full"
+            ))),
+        }
+    }
+}
+
+impl Display for TlsMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Disable => "disable",
+            Self::Prefer => "prefer",
+            Self::Require => "require",
+            Self::VerifyCa => "verify-ca",
+            Self::VerifyFull => "verify-full",
+        })
+    }
+}
+
+/// How connecting to the database is retried, such as while it is still starting up next to
+/// the app. The delay between attempts starts at `initial_delay` and is multiplied by `factor`
+/// after every attempt.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// The number of times to try connecting, including the first.
+    pub max_attempts: u32,
+    /// Seconds to wait after the first failed attempt, which may be fractional.
+    pub initial_delay: f64,
+    pub factor: f64,
+    /// Seconds after which to stop retrying, however many attempts are left.
+    pub deadline: Option<u64>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_delay: 0.5,
+            factor: 2.0,
+            deadline: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TursoConfig {
+    pub url: String,
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteConfig {
+    pub path: String,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MysqlConfig {
+    pub connection_string: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub enum Database {
+    #[serde(alias = "postgres")]
+    Postgres,
+    #[serde(alias = "turso")]
+    Turso,
+    #[serde(alias = "sqlite")]
+    Sqlite,
+    #[serde(alias = "mysql", alias = "mariadb")]
+    Mysql,
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DmtConfig {
+    #[serde(default = "default_migration_config")]
+    pub migration: MigrationConfig,
+    #[serde(default)]
+    pub connection: ConnectionConfig,
+    pub env: Option<EnvConfig>,
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
+}
+
+/// The environment variable naming the profile to use when none is given explicitly.
+pub const PROFILE_ENV_VAR: &str = "DMT_ENV";
+
+/// The names a config file is looked for under when no path is given, in order of preference.
+pub const CONFIG_FILE_NAMES: [&str; 4] = [
+    "dmt.config.toml",
+    "dmt.config.yml",
+    "dmt.config.yaml",
+    "dmt.config.json",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Determines the format of a config file from its extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("yml" | "yaml") => Ok(Self::Yaml),
+            Some("json") => Ok(Self::Json),
+            _ => Err(ConfigError::UnrecognizedConfigFormat(format!(
+                "Could not determine the format of config file {}. \
+                 Expected a .toml, .yml, .yaml or .json extension",
+                path.display()
+            ))),
+        }
+    }
+}
+
+impl DmtConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<DmtConfig, ConfigError> {
+        Self::read_file(path, None)
+    }
+
+    /// [`DmtConfig::from_file`], with the profile chosen as by [`DmtConfig::parse_with_profile`].
+    pub fn from_file_with_profile(
+        path: impl AsRef<Path>,
+        profile: Option<&str>,
+    ) -> Result<DmtConfig, ConfigError> {
+        Self::read_file(path, profile_name(profile).as_deref())
+    }
+
+    fn read_file(path: impl AsRef<Path>, profile: Option<&str>) -> Result<DmtConfig, ConfigError> {
+        let format = ConfigFormat::from_path(&path)?;
+        let contents = fs::read_to_string(path)?;
+
+        Self::parse_with_environment(&contents, format, profile, &process_environment)
+    }
+
+    /// Reads the first of [`CONFIG_FILE_NAMES`] found in `dir`.
+    pub fn discover(dir: impl AsRef<Path>) -> Result<DmtConfig, ConfigError> {
+        Self::from_file(Self::locate(dir)?)
+    }
+
+    /// [`DmtConfig::discover`], with the profile chosen as by [`DmtConfig::parse_with_profile`].
+    pub fn discover_with_profile(
+        dir: impl AsRef<Path>,
+        profile: Option<&str>,
+    ) -> Result<DmtConfig, ConfigError> {
+        Self::from_file_with_profile(Self::locate(dir)?, profile)
+    }
+
+    fn locate(dir: impl AsRef<Path>) -> Result<PathBuf, ConfigError> {
+        Self::find_file(&dir).ok_or_else(|| {
+            ConfigError::FileError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No config file found in {}. Expected one of {}",
+                    dir.as_ref().display(),
+                    CONFIG_FILE_NAMES.join(", ")
+                ),
+            ))
+        })
+    }
+
+    /// Returns the path of the first of [`CONFIG_FILE_NAMES`] that exists in `dir`.
+    pub fn find_file(dir: impl AsRef<Path>) -> Option<PathBuf> {
+        CONFIG_FILE_NAMES
+            .iter()
+            .map(|name| dir.as_ref().join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Parses a config and resolves the `${VAR}` and `${VAR:-default}` references in its string
+    /// values.
+    ///
+    /// Variables are looked up in the process environment first, then in the `.env` file named
+    /// by `env.file`, then in `env.vars`, before falling back to the default. A variable that is
+    /// found in none of them and has no default is a [`ConfigError::UnresolvedVariable`].
+    pub fn parse(s: &str, format: ConfigFormat) -> Result<DmtConfig, ConfigError> {
+        Self::parse_with_environment(s, format, None, &process_environment)
+    }
+
+    /// Parses a config, applies the profile named by `profile`, or by the `DMT_ENV` environment
+    /// variable if `profile` is `None`, and only then resolves variables as [`DmtConfig::parse`]
+    /// does. A base value that the profile replaces is never resolved, so the variables it uses
+    /// need not be set.
+    pub fn parse_with_profile(
+        s: &str,
+        format: ConfigFormat,
+        profile: Option<&str>,
+    ) -> Result<DmtConfig, ConfigError> {
+        Self::parse_with_environment(
+            s,
+            format,
+            profile_name(profile).as_deref(),
+            &process_environment,
+        )
+    }
+
+    /// Checks that `s` is a valid config without resolving its variables. This is how `migrate!`
+    /// reports config errors at compile time, when the variables are not known yet.
+    pub fn check(s: &str, format: ConfigFormat) -> Result<(), ConfigError> {
+        Self::parse_raw(s, format).map(drop)
+    }
+
+    /// Parses a config, applies `profile` if there is one, and then resolves variables, looking
+    /// them up in `environment` instead of the environment of the process.
+    fn parse_with_environment(
+        s: &str,
+        format: ConfigFormat,
+        profile: Option<&str>,
+        environment: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<DmtConfig, ConfigError> {
+        let mut config = Self::parse_raw(s, format)?;
+        if let Some(name) = profile {
+            let profile = config.take_profile(name)?;
+            config.apply(profile);
+        }
+
+        let variables = Variables::new(config.env.as_ref(), environment)?;
+        for (key, value) in config.string_values_mut() {
+            variables.interpolate(key, value)?;
+        }
+
+        Ok(config)
+    }
+
+    fn parse_raw(s: &str, format: ConfigFormat) -> Result<DmtConfig, ConfigError> {
+        match format {
+            ConfigFormat::Toml => {
+                toml::from_str(s).map_err(|err| ConfigError::ParseError(err.to_string()))
+            }
+            ConfigFormat::Yaml => {
+                serde_yaml::from_str(s).map_err(|err| ConfigError::ParseError(err.to_string()))
+            }
+            ConfigFormat::Json => {
+                serde_json::from_str(s).map_err(|err| ConfigError::ParseError(err.to_string()))
+            }
+        }
+    }
+
+    /// Applies the profile named by `name`, or by the `DMT_ENV` environment variable if `name` is
+    /// `None`. The base config is returned unchanged if neither names a profile.
+    ///
+    /// The variables of the base config have already been resolved by then; use
+    /// [`DmtConfig::parse_with_profile`] to leave those that the profile replaces unset.
+    pub fn select_profile(self, name: Option<&str>) -> Result<DmtConfig, ConfigError> {
+        match profile_name(name) {
+            Some(name) => self.with_profile(&name),
+            None => Ok(self),
+        }
+    }
+
+    /// Replaces the settings of the base config with those given in the profile `name`.
+    pub fn with_profile(mut self, name: &str) -> Result<DmtConfig, ConfigError> {
+        let mut profile = self.take_profile(name)?;
+
+        // Profiles are only interpolated once selected, so that the variables of the other
+        // profiles do not need to be set.
+        let variables = Variables::new(self.env.as_ref(), &process_environment)?;
+        for (key, value) in profile.string_values_mut() {
+            variables.interpolate(key, value)?;
+        }
+        self.apply(profile);
+
+        Ok(self)
+    }
+
+    fn take_profile(&mut self, name: &str) -> Result<ProfileConfig, ConfigError> {
+        self.profiles
+            .remove(name)
+            .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))
+    }
+
+    fn apply(&mut self, profile: ProfileConfig) {
+        let migration = profile.migration.unwrap_or_default();
+
+        if let Some(path) = migration.migration_path {
+            self.migration.migration_path = path;
+        }
+        if let Some(lock_timeout) = migration.lock_timeout {
+            self.migration.lock_timeout = lock_timeout;
+        }
+        if let Some(single_file) = migration.single_file {
+            self.migration.single_file = single_file;
+        }
+        if let Some(table) = migration.table {
+            self.migration.table = table;
+        }
+        if let Some(schema) = migration.schema {
+            self.migration.schema = Some(schema);
+        }
+
+        self.connection
+            .merge(profile.connection.unwrap_or_default());
+    }
+
+    /// The values of the base config that may contain `${VAR}` references.
+    fn string_values_mut(&mut self) -> Vec<(&'static str, &mut String)> {
+        let mut values = vec![(
+            "migration.migrationPath",
+            &mut self.migration.migration_path,
+        )];
+        values.extend(self.connection.string_values_mut());
+
+        values
+    }
+}
+
+impl FromStr for DmtConfig {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, ConfigFormat::Toml)
+    }
+}
+
+/// The profile named by `name`, or by the `DMT_ENV` environment variable if `name` is `None`.
+fn profile_name(name: Option<&str>) -> Option<String> {
+    match name {
+        Some(name) => Some(name.to_string()),
+        None => env::var(PROFILE_ENV_VAR)
+            .ok()
+            .filter(|name| !name.is_empty()),
+    }
+}
+
+fn process_environment(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+fn default_migration_path() -> String {
+    "./migrations/".to_string()
+}
+
+fn default_lock_timeout() -> u64 {
+    DEFAULT_LOCK_TIMEOUT
+}
+
+fn default_table() -> String {
+    DEFAULT_TABLE.to_string()
+}
+
+fn default_migration_config() -> MigrationConfig {
+    MigrationConfig {
+        migration_path: default_migration_path(),
+        lock_timeout: default_lock_timeout(),
+        single_file: false,
+        table: default_table(),
+        schema: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{ConfigFormat, Database, DmtConfig, RetryConfig, TlsMode};
+    use crate::ConfigError;
+
+    #[test]
+    fn formats_parse_to_the_same_config() {
+        let toml = r#"
+[migration]
+migrationPath = "./db/migrations"
+
+[connection]
+database = "postgres"
+
+[connection.postgres]
+connectionString = "host=localhost"
+"#;
+
+        let yaml = r#"
+migration:
+  migrationPath: "./db/migrations"
+connection:
+  database: postgres
+  postgres:
+    connectionString: "host=localhost"
+"#;
+
+        let json = r#"{
+  "migration": { "migrationPath": "./db/migrations" },
+  "connection": {
+    "database": "postgres",
+    "postgres": { "connectionString": "host=localhost" }
+  }
+}"#;
+
+        let expected = DmtConfig::parse(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(expected.connection.database, Some(Database::Postgres));
+        assert_eq!(expected.migration.migration_path, "./db/migrations");
+
+        assert_eq!(
+            DmtConfig::parse(yaml, ConfigFormat::Yaml).unwrap(),
+            expected
+        );
+        assert_eq!(
+            DmtConfig::parse(json, ConfigFormat::Json).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn format_is_chosen_by_extension() {
+        assert_eq!(
+            ConfigFormat::from_path("dmt.config.yml").unwrap(),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path("config/dmt.json").unwrap(),
+            ConfigFormat::Json
+        );
+        assert!(ConfigFormat::from_path("dmt.config.ini").is_err());
+        assert!(ConfigFormat::from_path("dmt").is_err());
+    }
+
+    #[test]
+    fn discovers_config_files_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(DmtConfig::find_file(dir.path()).is_none());
+        assert!(DmtConfig::discover(dir.path()).is_err());
+
+        fs::write(
+            dir.path().join("dmt.config.yaml"),
+            "connection:\n  database: sqlite\n",
+        )
+        .unwrap();
+        assert_eq!(
+            DmtConfig::find_file(dir.path()).unwrap(),
+            dir.path().join("dmt.config.yaml")
+        );
+
+        fs::write(
+            dir.path().join("dmt.config.toml"),
+            "[connection]\ndatabase = \"turso\"\n",
+        )
+        .unwrap();
+        let config = DmtConfig::discover(dir.path()).unwrap();
+        assert_eq!(config.connection.database, Some(Database::Turso));
+    }
+
+    #[test]
+    fn interpolates_variables_by_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+        fs::write(
+            &env_file,
+            "# credentials\nexport DMT_TEST_USER=file_user\nDMT_TEST_PASSWORD=\"from file\"\n",
+        )
+        .unwrap();
+
+        let config = format!(
+            r#"
+[migration]
+migrationPath = "${{DMT_TEST_DIR:-./migrations}}"
+
+[connection.postgres]
+connectionString = "user=${{DMT_TEST_USER}} password='${{DMT_TEST_PASSWORD}}' dbname=${{DMT_TEST_DB}} $${{literal}}"
+
+[env]
+file = "{}"
+
+[env.vars]
+DMT_TEST_PASSWORD = "from vars"
+DMT_TEST_DB = "from_vars"
+"#,
+            env_file.display()
+        );
+
+        let environment = |name: &str| (name == "DMT_TEST_USER").then(|| "env_user".to_string());
+        let config =
+            DmtConfig::parse_with_environment(&config, ConfigFormat::Toml, None, &environment)
+                .unwrap();
+
+        assert_eq!(config.migration.migration_path, "./migrations");
+        assert_eq!(
+            config.connection.postgres.unwrap().connection_string,
+            "user=env_user password='from file' dbname=from_vars ${literal}"
+        );
+    }
+
+    #[test]
+    fn profiles_override_the_base_config() {
+        let config = r#"
+[migration]
+migrationPath = "./migrations"
+
+[connection]
+database = "sqlite"
+
+[connection.sqlite]
+path = "dev.db"
+
+[profiles.staging.migration]
+lockTimeout = 5
+
+[profiles.staging.connection]
+database = "postgres"
+
+[profiles.staging.connection.postgres]
+connectionString = "host=staging"
+
+[profiles.prod.connection.postgres]
+connectionString = "password=${DMT_TEST_UNSET_PROD_PASSWORD}"
+"#;
+
+        let base = DmtConfig::parse(config, ConfigFormat::Toml).unwrap();
+        assert_eq!(base.connection.database, Some(Database::Sqlite));
+
+        let staging = base.with_profile("staging").unwrap();
+        assert_eq!(staging.connection.database, Some(Database::Postgres));
+        assert_eq!(
+            staging.connection.postgres.unwrap().connection_string,
+            "host=staging"
+        );
+        assert_eq!(staging.connection.sqlite.unwrap().path, "dev.db");
+        assert_eq!(staging.migration.migration_path, "./migrations");
+        assert_eq!(staging.migration.lock_timeout, 5);
+
+        let base = DmtConfig::parse(config, ConfigFormat::Toml).unwrap();
+        assert!(matches!(
+            base.with_profile("qa"),
+            Err(ConfigError::UnknownProfile(name)) if name == "qa"
+        ));
+    }
+
+    #[test]
+    fn profiles_are_applied_before_variables_are_resolved() {
+        let config = r#"
+[connection.postgres]
+connectionString = "password=${DEV_PASSWORD}"
+
+[profiles.prod.connection.postgres]
+connectionString = "password=${PROD_PASSWORD}"
+"#;
+        let environment = |name: &str| (name == "PROD_PASSWORD").then(|| "hunter2".to_string());
+
+        let prod = DmtConfig::parse_with_environment(
+            config,
+            ConfigFormat::Toml,
+            Some("prod"),
+            &environment,
+        )
+        .unwrap();
+        assert_eq!(
+            prod.connection.postgres.unwrap().connection_string,
+            "password=hunter2"
+        );
+
+        assert!(matches!(
+            DmtConfig::parse_with_environment(config, ConfigFormat::Toml, None, &environment),
+            Err(ConfigError::UnresolvedVariable(name)) if name == "DEV_PASSWORD"
+        ));
+    }
+
+    #[test]
+    fn configs_are_checked_without_resolving_variables() {
+        let config = r#"
+[connection]
+database = "postgres"
+
+[connection.postgres]
+connectionString = "password=${DMT_TEST_UNSET_PASSWORD}"
+"#;
+        DmtConfig::check(config, ConfigFormat::Toml).unwrap();
+
+        for (config, expected) in [
+            (
+                "[connection]\ndatabase = \"oracle\"",
+                "unknown variant `oracle`",
+            ),
+            (
+                "[migration]\nmigrationPth = \"db\"",
+                "unknown field `migrationPth`",
+            ),
+            (
+                "[connection.postgres]\nconnectionString = \"\"\nhots = \"db\"",
+                "unknown field `hots`",
+            ),
+        ] {
+            let err = DmtConfig::check(config, ConfigFormat::Toml).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn parses_postgres_tls_settings() {
+        let config = r#"
+[connection.postgres]
+connectionString = "host=db sslmode=require"
+
+[connection.postgres.tls]
+mode = "verify-full"
+caFile = "${DMT_TEST_CERT_DIR}/ca.crt"
+"#;
+
+        let environment =
+            |name: &str| (name == "DMT_TEST_CERT_DIR").then(|| "/etc/dmt".to_string());
+        let tls = DmtConfig::parse_with_environment(config, ConfigFormat::Toml, None, &environment)
+            .unwrap()
+            .connection
+            .postgres
+            .unwrap()
+            .tls
+            .unwrap();
+
+        assert_eq!(tls.mode, Some(TlsMode::VerifyFull));
+        assert_eq!(tls.ca_file.as_deref(), Some("/etc/dmt/ca.crt"));
+        assert_eq!(tls.client_cert, None);
+        assert!("verify-none".parse::<TlsMode>().is_err());
+    }
+
+    #[test]
+    fn parses_retry_settings() {
+        let config = r#"
+[connection.retry]
+maxAttempts = 5
+initialDelay = 0.25
+
+[profiles.ci.connection.retry]
+deadline = 30
+"#;
+
+        let config = DmtConfig::parse(config, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            config.connection.retry,
+            Some(RetryConfig {
+                max_attempts: 5,
+                initial_delay: 0.25,
+                ..RetryConfig::default()
+            })
+        );
+
+        let retry = config.select_profile(Some("ci")).unwrap().connection.retry;
+        assert_eq!(
+            retry,
+            Some(RetryConfig {
+                deadline: Some(30),
+                ..RetryConfig::default()
+            })
+        );
+    }
+
+    #[test]
+    fn unresolved_variables_are_errors() {
+        let config = r#"
+[connection.turso]
+url = "libsql://db.turso.io"
+token = "${DMT_TEST_UNSET_TOKEN}"
+"#;
+
+        let err = DmtConfig::parse(config, ConfigFormat::Toml).unwrap_err();
+
+        assert!(
+            matches!(&err, ConfigError::UnresolvedVariable(name) if name == "DMT_TEST_UNSET_TOKEN")
+        );
+        assert!(err.to_string().contains("DMT_TEST_UNSET_TOKEN"));
+
+        let config = r#"
+[connection.postgres]
+connectionString = "password=hunter2 user=${DMT_TEST_USER"
+"#;
+
+        let err = DmtConfig::parse(config, ConfigFormat::Toml)
+            .unwrap_err()
+            .to_string();
+
+        assert!(
+            err.contains("connection.postgres.connectionString"),
+            "{err}"
+        );
+        assert!(!err.contains("hunter2"), "{err}");
+    }
+}
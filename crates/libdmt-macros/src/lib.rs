@@ -1,100 +1,398 @@
-use std::fs::File;
-use std::io::Read;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::{env, io};
 
+use libdmt_config::{ConfigError, ConfigFormat, DmtConfig};
 use proc_macro::TokenStream;
-use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
 use quote::quote;
-use syn::parse::Parser;
-use syn::punctuated::Punctuated;
-use syn::spanned::Spanned;
-use syn::Token;
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, LitStr, Token};
 
+/// The `migrationPath` used when a config does not set one.
+const DEFAULT_MIGRATION_PATH: &str = "./migrations/";
+
+/// Migrates the database of a dmt config, returning a
+/// `Result<libdmt::MigrationReport, libdmt::DmtError>`.
+///
+/// Takes an optional path to the config file, relative to the crate root, and otherwise uses the
+/// first `dmt.config.{toml,yml,yaml,json}` found there. A profile can be chosen with
+/// `profile = "name"`, e.g. `migrate!("config/dmt.toml", profile = "production")`; without one,
+/// the profile named by `DMT_ENV` at runtime is used, if any.
+///
+/// The config is embedded in the binary, and a config that is not a valid dmt config, such as one
+/// with an unknown key or database, is a compile error. Its `${VAR}` references are resolved at
+/// runtime.
 #[proc_macro]
 pub fn migrate(input: TokenStream) -> TokenStream {
-    let args = <Punctuated<syn::LitStr, Token![,]>>::parse_terminated
-        .parse(input)
-        .expect("expected macro to be called with a comma-separated list of string literals");
-
-    if !args.is_empty() {
-        return syn::Error::new(args.span(), "migrate! does not take any arguments")
-            .to_compile_error()
-            .into();
+    let args = parse_macro_input!(input as MigrateArgs);
+
+    migrate_inner(args.config(), args.profile()).into()
+}
+
+/// Like [`migrate!`], but panics with the error if the migration fails, and otherwise evaluates to
+/// the `libdmt::MigrationReport`.
+#[proc_macro]
+pub fn migrate_or_panic(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as MigrateArgs);
+    let migrate = migrate_inner(args.config(), args.profile());
+
+    quote! {
+        match #migrate {
+            ::std::result::Result::Ok(report) => report,
+            ::std::result::Result::Err(err) => ::std::panic!("Failed to migrate the database: {}", err),
+        }
     }
+    .into()
+}
 
-    migrate_inner().into()
+/// The arguments of `migrate!`: an optional config file path, then an optional
+/// `profile = "name"`.
+#[derive(Default)]
+struct MigrateArgs {
+    config: Option<LitStr>,
+    profile: Option<LitStr>,
 }
 
-fn migrate_inner() -> TokenStream2 {
+impl MigrateArgs {
+    fn config(&self) -> Option<String> {
+        self.config.as_ref().map(LitStr::value)
+    }
+
+    fn profile(&self) -> Option<String> {
+        self.profile.as_ref().map(LitStr::value)
+    }
+}
+
+impl Parse for MigrateArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = MigrateArgs::default();
+
+        if input.peek(LitStr) {
+            args.config = Some(input.parse()?);
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        if !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key != "profile" {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "expected a config file path or `profile = \"name\"`",
+                ));
+            }
+
+            input.parse::<Token![=]>()?;
+            args.profile = Some(input.parse()?);
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        if !input.is_empty() {
+            return Err(input.error("migrate! takes at most a config file path and a profile"));
+        }
+
+        Ok(args)
+    }
+}
+
+/// Embeds the migrations of a directory in the binary, as a `libdmt::EmbeddedMigrations` that can
+/// be run instead of the directory, e.g. `static MIGRATIONS: EmbeddedMigrations =
+/// embed_migrations!();` and `libdmt::run_migrations(&mut db, &MIGRATIONS)`.
+///
+/// Takes the directory relative to the crate root, and defaults to `./migrations/`, the default
+/// `migrationPath`. The crate is rebuilt whenever an embedded file changes, but Cargo cannot
+/// watch the directory itself, so for new migrations to be picked up without a `cargo clean`,
+/// add a build script that prints `cargo:rerun-if-changed=migrations`.
+#[proc_macro]
+pub fn embed_migrations(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as EmbedArgs);
+
+    embed_migrations_inner(args.path.as_ref().map(LitStr::value)).into()
+}
+
+/// The argument of `embed_migrations!`: an optional migrations directory.
+struct EmbedArgs {
+    path: Option<LitStr>,
+}
+
+impl Parse for EmbedArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = if input.is_empty() {
+            None
+        } else {
+            Some(input.parse()?)
+        };
+
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+        if !input.is_empty() {
+            return Err(input.error("embed_migrations! takes at most a migrations directory"));
+        }
+
+        Ok(EmbedArgs { path })
+    }
+}
+
+/// Expands to an `EmbeddedMigrations` holding every migration in `path`, or in `./migrations/`
+/// if no path is given, relative to the crate root.
+fn embed_migrations_inner(path: Option<String>) -> TokenStream2 {
     let Ok(dir_path) = env::var("CARGO_MANIFEST_DIR") else {
         return quote! {
-            compile_error!("Could not get path to current dir to find dmt config file.");
+            compile_error!("Could not get path to current dir to find the migrations directory.");
         };
     };
 
-    let mut path = PathBuf::from(dir_path);
-    path.push("dmt.config.toml");
+    let dir = PathBuf::from(dir_path).join(path.as_deref().unwrap_or(DEFAULT_MIGRATION_PATH));
 
-    let Ok(contents) = get_file_contents(path) else {
-        return quote! {
-            compile_error!("Could not read dmt config file.");
-        };
+    let migrations = match read_migration_dir(&dir) {
+        Ok(migrations) => migrations,
+        Err(err) => {
+            let message = format!(
+                "Could not read migrations directory `{}`: {err}",
+                dir.display()
+            );
+            return quote! { compile_error!(#message); };
+        }
     };
 
     quote! {
+        ::libdmt::EmbeddedMigrations::new(&[#(#migrations),*])
+    }
+}
+
+/// Lists the migrations in `dir` the same way `MigrationDir` does, as `EmbeddedMigration`s that
+/// include the contents of their files.
+fn read_migration_dir(dir: &Path) -> Result<Vec<TokenStream2>, io::Error> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut migrations = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        if file_type.is_dir() {
+            let up = path.join("up.sql").to_string_lossy().to_string();
+            let down = path.join("down.sql").to_string_lossy().to_string();
+
+            migrations.push(quote! {
+                ::libdmt::EmbeddedMigration::Dir {
+                    name: #file_name,
+                    up: include_str!(#up),
+                    down: include_str!(#down),
+                }
+            });
+        } else if let Some(name) = file_name
+            .strip_suffix(".sql")
+            .filter(|_| file_type.is_file())
         {
-            use ::libdmt::{DmtConfig as __DmtConfig, MigrationDatabase as __MigrationDb, run_migrations as __run_dmt};
-            use ::std::{str::FromStr as __FromStr, convert::TryFrom as __TryFrom};
+            let contents = path.to_string_lossy().to_string();
 
-            let __dmt_config_contents = #contents;
-            let __dmt_config = <__DmtConfig as __FromStr>::from_str(__dmt_config_contents).unwrap();
-            let mut __dmt_db = <__MigrationDb as __TryFrom<&__DmtConfig>>::try_from(&__dmt_config).unwrap();
-            __run_dmt(&mut __dmt_db, &__dmt_config.migration.migration_path).unwrap();
+            migrations.push(quote! {
+                ::libdmt::EmbeddedMigration::SingleFile {
+                    name: #name,
+                    contents: include_str!(#contents),
+                }
+            });
         }
     }
+
+    Ok(migrations)
 }
 
-fn get_file_contents(path: impl AsRef<Path>) -> Result<String, io::Error> {
-    let mut config_contents = String::new();
+/// Expands to an expression that migrates the database of the config at `config`, or of the
+/// config found in the crate root, using the profile `profile`, or the one named by `DMT_ENV` at
+/// runtime if no profile is given.
+fn migrate_inner(config: Option<String>, profile: Option<String>) -> TokenStream2 {
+    let Ok(dir_path) = env::var("CARGO_MANIFEST_DIR") else {
+        return quote! {
+            compile_error!("Could not get path to current dir to find dmt config file.");
+        };
+    };
+    let dir_path = PathBuf::from(dir_path);
+
+    let (path, format) = match config {
+        Some(config) => {
+            let path = dir_path.join(&config);
+            let Some(format) = config_format(&path) else {
+                let message = format!(
+                    "Unrecognized config format `{config}`. Expected a .toml, .yml, .yaml or .json file."
+                );
+                return quote! { compile_error!(#message); };
+            };
 
-    File::open(path)?.read_to_string(&mut config_contents)?;
+            (path, format)
+        }
+        None => {
+            let Some(found) = find_config_file(&dir_path) else {
+                return quote! {
+                    compile_error!("Could not find a dmt.config.{toml,yml,yaml,json} file.");
+                };
+            };
+
+            found
+        }
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            let message = format!("Could not read dmt config file `{}`: {err}", path.display());
+            return quote! { compile_error!(#message); };
+        }
+    };
+
+    if let Err(err) = check_config(&contents, format) {
+        let message = format!("Invalid dmt config file `{}`: {err}", path.display());
+        return quote! { compile_error!(#message); };
+    }
+
+    // Including the file rather than its contents rebuilds the crate when the config changes.
+    let path = path.to_string_lossy().to_string();
+    let format = Ident::new(format, Span::call_site());
+    let profile = match profile {
+        Some(profile) => quote! { ::std::option::Option::Some(#profile) },
+        None => quote! { ::std::option::Option::None },
+    };
+
+    quote! {
+        (|| -> ::std::result::Result<::libdmt::MigrationReport, ::libdmt::DmtError> {
+            use ::libdmt::{ConfigFormat as __ConfigFormat, DmtConfig as __DmtConfig, DmtError as __DmtError, MigrationDatabase as __MigrationDb, Migrator as __Migrator};
+            use ::std::convert::TryFrom as __TryFrom;
 
-    Ok(config_contents)
+            let __dmt_config_contents = include_str!(#path);
+            let __dmt_config = __DmtConfig::parse_with_profile(__dmt_config_contents, __ConfigFormat::#format, #profile)
+                .map_err(__DmtError::ConfigError)?;
+            let mut __dmt_db = <__MigrationDb as __TryFrom<&__DmtConfig>>::try_from(&__dmt_config)?;
+            let __dmt_report = __Migrator::from_config(&__dmt_config.migration).run(&mut __dmt_db)?;
+
+            ::std::result::Result::Ok(__dmt_report)
+        })()
+    }
+}
+
+/// Finds the config file the same way `DmtConfig::discover` does, returning its path and the
+/// name of its `ConfigFormat` variant.
+fn find_config_file(dir: &Path) -> Option<(PathBuf, &'static str)> {
+    [
+        "dmt.config.toml",
+        "dmt.config.yml",
+        "dmt.config.yaml",
+        "dmt.config.json",
+    ]
+    .into_iter()
+    .map(|name| dir.join(name))
+    .find(|path| path.is_file())
+    .and_then(|path| config_format(&path).map(|format| (path, format)))
+}
+
+/// The name of the `ConfigFormat` variant for `path`, chosen by its extension the same way
+/// `ConfigFormat::from_path` does.
+fn config_format(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()? {
+        "toml" => Some("Toml"),
+        "yml" | "yaml" => Some("Yaml"),
+        "json" => Some("Json"),
+        _ => None,
+    }
+}
+
+/// Checks that `contents` is a valid dmt config in `format`. Its `${VAR}` references are only
+/// resolved at runtime.
+fn check_config(contents: &str, format: &str) -> Result<(), ConfigError> {
+    let format = match format {
+        "Toml" => ConfigFormat::Toml,
+        "Yaml" => ConfigFormat::Yaml,
+        _ => ConfigFormat::Json,
+    };
+
+    DmtConfig::check(contents, format)
 }
 
 #[cfg(test)]
 mod test {
+    use std::env;
+    use std::path::PathBuf;
+
     use quote::quote;
 
-    use crate::migrate_inner;
+    use crate::{check_config, migrate_inner, EmbedArgs};
 
     #[test]
     fn migrate_inner_correct() {
-        let config = r#"[migration]
-migrationPath = "./migrationsssss"
+        let path = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("dmt.config.toml")
+            .to_string_lossy()
+            .to_string();
 
-[connection]
-database = "turso"
+        let output = migrate_inner(None, None);
+        let expected = quote! {
+            (|| -> ::std::result::Result<::libdmt::MigrationReport, ::libdmt::DmtError> {
+                use ::libdmt::{ConfigFormat as __ConfigFormat, DmtConfig as __DmtConfig, DmtError as __DmtError, MigrationDatabase as __MigrationDb, Migrator as __Migrator};
+                use ::std::convert::TryFrom as __TryFrom;
 
-[connection.turso]
-url = "TEST_URL"
-token = "TEST_TOKEN"
-"#;
+                let __dmt_config_contents = include_str!(#path);
+                let __dmt_config = __DmtConfig::parse_with_profile(__dmt_config_contents, __ConfigFormat::Toml, ::std::option::Option::None)
+                    .map_err(__DmtError::ConfigError)?;
+                let mut __dmt_db = <__MigrationDb as __TryFrom<&__DmtConfig>>::try_from(&__dmt_config)?;
+                let __dmt_report = __Migrator::from_config(&__dmt_config.migration).run(&mut __dmt_db)?;
 
-        let output = migrate_inner();
-        let expected = quote! {
-            {
-                use ::libdmt::{DmtConfig as __DmtConfig, MigrationDatabase as __MigrationDb, run_migrations as __run_dmt};
-                use ::std::{str::FromStr as __FromStr, convert::TryFrom as __TryFrom};
-
-                let __dmt_config_contents = #config;
-                let __dmt_config = <__DmtConfig as __FromStr>::from_str(__dmt_config_contents).unwrap();
-                let mut __dmt_db = <__MigrationDb as __TryFrom<&__DmtConfig>>::try_from(&__dmt_config).unwrap();
-                __run_dmt(&mut __dmt_db, &__dmt_config.migration.migration_path).unwrap();
-            }
+                ::std::result::Result::Ok(__dmt_report)
+            })()
         };
 
         assert_eq!(output.to_string(), expected.to_string());
     }
+
+    #[test]
+    fn invalid_config_is_a_compile_error() {
+        for (contents, format, expected) in [
+            (
+                "[migration\nmigrationPath = 1",
+                "Toml",
+                "invalid table header",
+            ),
+            (
+                "connection:\n  database: oracle",
+                "Yaml",
+                "unknown variant `oracle`",
+            ),
+            (
+                r#"{"migration": {"lockTimout": 5}}"#,
+                "Json",
+                "unknown field `lockTimout`",
+            ),
+        ] {
+            let err = check_config(contents, format).unwrap_err().to_string();
+            assert!(err.contains(expected), "{err}");
+        }
+
+        let output = migrate_inner(Some("missing.toml".to_string()), None).to_string();
+        assert!(output.starts_with("compile_error !"), "{output}");
+        assert!(output.contains("missing.toml"), "{output}");
+    }
+
+    #[test]
+    fn embed_migrations_takes_at_most_a_directory() {
+        assert!(syn::parse_str::<EmbedArgs>("").unwrap().path.is_none());
+        assert_eq!(
+            syn::parse_str::<EmbedArgs>(r#""./db/migrations","#)
+                .unwrap()
+                .path
+                .unwrap()
+                .value(),
+            "./db/migrations"
+        );
+
+        assert!(syn::parse_str::<EmbedArgs>("migrations").is_err());
+        assert!(syn::parse_str::<EmbedArgs>(r#""a", "b""#).is_err());
+    }
 }
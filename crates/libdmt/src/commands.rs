@@ -1,15 +1,88 @@
+mod migration_status;
 mod new_migration;
 mod rollback_migration;
 mod run_migrations;
+mod verify_migrations;
 
 use chrono::NaiveDateTime;
-pub use new_migration::new_migration;
-pub use rollback_migration::rollback_migrations;
-pub use run_migrations::run_migrations;
+pub(crate) use migration_status::status;
+pub use migration_status::{migration_status, MigrationState, MigrationStatus};
+pub use new_migration::{new_migration, new_single_file_migration};
+#[cfg(feature = "async")]
+pub use rollback_migration::rollback_migrations_async;
+pub(crate) use rollback_migration::{plan as plan_rollback, rollback};
+pub use rollback_migration::{rollback_migrations, RollbackTarget};
+pub(crate) use run_migrations::{plan as plan_run, run};
+pub use run_migrations::{run_migrations, run_migrations_to};
+#[cfg(feature = "async")]
+pub use run_migrations::{run_migrations_async, run_migrations_to_async};
+pub(crate) use verify_migrations::verify;
+pub use verify_migrations::verify_migrations;
 
+use std::time::Instant;
+
+use crate::migration::Definition;
+use crate::{Direction, MigrationError, MigrationEvent, MigrationId, Reporter};
+
+/// A migration that has been run, as recorded in the `migration` table.
 #[derive(Debug)]
-pub struct Migration {
+pub struct MigrationRecord {
     pub id: i32,
     pub name: String,
     pub time: NaiveDateTime,
+    /// The checksum of the migration's files when it was run, if it was recorded. Rust
+    /// migrations have none.
+    pub checksum: Option<String>,
+}
+
+/// A migration that a run or rollback would apply, together with the SQL it would execute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedMigration {
+    pub name: String,
+    pub direction: Direction,
+    /// The SQL the migration runs, or `None` for a migration written in Rust.
+    pub sql: Option<String>,
+    /// Whether the SQL runs inside a transaction. See [`MigrationStatus::transactional`].
+    pub transactional: bool,
+}
+
+/// Reports how running the migration `name` ended, passing on its error if it failed.
+fn report_outcome(
+    reporter: &(impl Reporter + ?Sized),
+    name: &str,
+    direction: Direction,
+    started: Instant,
+    result: Result<(), MigrationError>,
+) -> Result<(), MigrationError> {
+    match result {
+        Ok(()) => {
+            reporter.report(&MigrationEvent::Succeeded {
+                name,
+                direction,
+                duration: started.elapsed(),
+            });
+            Ok(())
+        }
+        Err(err) => {
+            reporter.report(&MigrationEvent::Failed {
+                name,
+                direction,
+                duration: started.elapsed(),
+                error: &err,
+            });
+            Err(err)
+        }
+    }
+}
+
+/// Finds the id of the migration that `version` refers to.
+fn find_target<'a>(
+    definitions: &'a [Definition],
+    version: &str,
+) -> Result<&'a MigrationId, MigrationError> {
+    definitions
+        .iter()
+        .map(|definition| &definition.id)
+        .find(|id| id.matches(version))
+        .ok_or_else(|| MigrationError::UnknownTarget(version.to_string()))
 }
@@ -0,0 +1,87 @@
+use chrono::NaiveDateTime;
+
+use crate::database::DatabaseConnection;
+use crate::io::MigrationSource;
+use crate::migration::Definition;
+use crate::{MigrationError, Migrator};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationState {
+    /// The migration has been run, at the recorded time.
+    Applied(NaiveDateTime),
+    /// The migration exists but has not been run yet.
+    Pending,
+    /// The migration has been run, at the recorded time, but no longer exists.
+    Missing(NaiveDateTime),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationStatus {
+    pub name: String,
+    pub state: MigrationState,
+    /// Whether the migration runs inside a transaction. Rust migrations and migrations marked
+    /// with a `-- dmt:no-transaction` line are not atomic: if one of their statements fails, the
+    /// statements before it stay applied. Missing migrations are reported as transactional,
+    /// since their SQL can no longer be read.
+    pub transactional: bool,
+}
+
+pub fn migration_status(
+    db: &mut impl DatabaseConnection,
+    source: impl Into<MigrationSource>,
+) -> Result<Vec<MigrationStatus>, MigrationError> {
+    Migrator::new(source).status(db)
+}
+
+pub(crate) fn status(
+    db: &mut impl DatabaseConnection,
+    definitions: &[Definition],
+) -> Result<Vec<MigrationStatus>, MigrationError> {
+    let ran_migrations = if db.migration_table_exists()? {
+        db.get_migrations()?
+    } else {
+        Vec::new()
+    };
+
+    let names: Vec<String> = definitions
+        .iter()
+        .map(|definition| definition.name())
+        .collect();
+
+    let mut statuses: Vec<MigrationStatus> = definitions
+        .iter()
+        .zip(&names)
+        .map(|(definition, name)| {
+            let state = match ran_migrations
+                .iter()
+                .find(|migration| &migration.name == name)
+            {
+                Some(migration) => MigrationState::Applied(migration.time),
+                None => MigrationState::Pending,
+            };
+
+            MigrationStatus {
+                name: name.clone(),
+                state,
+                transactional: definition.transactional(),
+            }
+        })
+        .collect();
+
+    statuses.extend(
+        ran_migrations
+            .iter()
+            .filter(|migration| !names.contains(&migration.name))
+            .map(|migration| MigrationStatus {
+                name: migration.name.clone(),
+                state: MigrationState::Missing(migration.time),
+                transactional: true,
+            }),
+    );
+
+    // `YYYYMMDDHHMMSS_name` sorts the same way as `MigrationId`, and leaves any malformed names
+    // recorded in the database listable.
+    statuses.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(statuses)
+}
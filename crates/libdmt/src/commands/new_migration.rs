@@ -1,10 +1,11 @@
 use std::{
     fs::{self, File},
     io::Write,
-    path::{Path, PathBuf},
+    path::Path,
 };
 
-use crate::MigrationError;
+use crate::io::{DOWN_MARKER, UP_MARKER};
+use crate::{MigrationError, MigrationId};
 
 static DEFAULT_SQL: &str = r"
 
@@ -26,8 +27,8 @@ pub fn new_migration(name: &str, path: impl AsRef<Path>) -> Result<(), Migration
     //}
     let mut migrations_path = path.as_ref().to_path_buf();
 
-    let now = chrono::Utc::now();
-    let new_migrations_folder_name = format!("{}_{}", now.format("%Y%m%d%H%M%S"), name);
+    let now = chrono::Utc::now().naive_utc();
+    let new_migrations_folder_name = MigrationId::new(now, name).to_string();
 
     migrations_path.push(&new_migrations_folder_name);
 
@@ -55,3 +56,25 @@ pub fn new_migration(name: &str, path: impl AsRef<Path>) -> Result<(), Migration
 
     Ok(())
 }
+
+/// Creates a new migration as a single `YYYYMMDDHHMMSS_name.sql` file, with its `up` and `down`
+/// sections marked by `-- dmt:up` and `-- dmt:down` lines.
+pub fn new_single_file_migration(name: &str, path: impl AsRef<Path>) -> Result<(), MigrationError> {
+    let now = chrono::Utc::now().naive_utc();
+    let new_migration_name = MigrationId::new(now, name).to_string();
+
+    fs::create_dir_all(&path)?;
+
+    let mut file = File::create(path.as_ref().join(format!("{}.sql", new_migration_name)))?;
+    let sql = format!(
+        "-- {}\n\n{}\n{}\n{}\n{}",
+        new_migration_name,
+        UP_MARKER,
+        DEFAULT_SQL.trim_start(),
+        DOWN_MARKER,
+        DEFAULT_SQL.trim_start()
+    );
+    file.write_all(sql.as_bytes())?;
+
+    Ok(())
+}
@@ -1,59 +1,143 @@
-use std::path::Path;
+use std::cmp::Reverse;
+use std::time::Instant;
 
-use crate::MigrationError;
-use crate::{database::DatabaseConnection, io::MigrationDir};
+use super::{find_target, report_outcome, MigrationRecord, PlannedMigration};
+use crate::database::{AsyncDatabaseConnection, DatabaseConnection};
+use crate::io::MigrationSource;
+use crate::migration::Definition;
+use crate::runner::Runner;
+use crate::{
+    Direction, MigrationError, MigrationEvent, MigrationId, MigrationReport, Migrator, Reporter,
+};
+
+/// How many applied migrations [`rollback_migrations`] should revert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RollbackTarget {
+    /// Revert this many of the most recently applied migrations.
+    Steps(usize),
+    /// Revert every applied migration.
+    All,
+    /// Revert every applied migration newer than the migration with this version, which must
+    /// exist on disk.
+    To(String),
+}
+
+impl Default for RollbackTarget {
+    fn default() -> Self {
+        Self::Steps(1)
+    }
+}
 
 pub fn rollback_migrations(
     db: &mut impl DatabaseConnection,
-    path: &impl AsRef<Path>,
-) -> Result<(), MigrationError> {
-    if !db.migration_table_exists()? {
-        println!("   No migrations have yet been run. Thus, none can be rolled back. ");
-        return Ok(());
+    source: impl Into<MigrationSource>,
+    target: RollbackTarget,
+) -> Result<MigrationReport, MigrationError> {
+    Migrator::new(source).rollback(db, target)
+}
+
+/// The async counterpart of [`rollback_migrations`]. Fails without reverting anything if one of
+/// the migrations to revert is written in Rust.
+#[cfg(feature = "async")]
+pub async fn rollback_migrations_async(
+    db: &mut impl AsyncDatabaseConnection,
+    source: impl Into<MigrationSource>,
+    target: RollbackTarget,
+) -> Result<MigrationReport, MigrationError> {
+    Migrator::new(source).rollback_async(db, target).await
+}
+
+pub(crate) async fn rollback(
+    db: &mut impl Runner,
+    definitions: &[Definition<'_>],
+    target: RollbackTarget,
+    reporter: &(impl Reporter + ?Sized),
+) -> Result<MigrationReport, MigrationError> {
+    let mut report = MigrationReport::new(Direction::Down);
+
+    let connection = db.connection();
+    if !connection.migration_table_exists().await? {
+        reporter.report(&MigrationEvent::NothingToRollback);
+        return Ok(report);
     }
 
-    let ran_migrations_db: Vec<String> = db
-        .get_migrations()?
-        .iter()
-        .map(|migration| migration.name.clone())
-        .collect();
+    connection.upgrade_migrations_table().await?;
 
-    let migration_root_dir = MigrationDir::new(path);
+    let ran_migrations = connection.get_migrations().await?;
 
-    let mut migration_dirs = migration_root_dir.get_migration_dir_names()?;
+    if ran_migrations.is_empty() {
+        reporter.report(&MigrationEvent::NothingToRollback);
+        return Ok(report);
+    }
 
-    let ran_migration_names = migration_dirs
-        .iter_mut()
-        .filter(|dir_name| ran_migrations_db.contains(dir_name));
+    let reverting = reverting(ran_migrations, definitions, target)?;
+    db.check(&reverting)?;
 
-    let mut ran = false;
-    for migration in ran_migration_names {
-        ran = true;
-        let path = format!("{}/down.sql", migration);
-        let down_sql = migration_root_dir.get_file_contents(&path)?;
+    for migration in reverting {
+        let name = migration.name();
 
-        match db.execute_sql(&down_sql) {
-            Ok(()) => rollback_success(migration),
-            Err(err) => {
-                rollback_failure(migration);
-                return Err(err.into());
-            }
-        }
+        reporter.report(&MigrationEvent::Started {
+            name: &name,
+            direction: Direction::Down,
+        });
+        let started = Instant::now();
 
-        db.remove_migration_by_name(migration)?;
-    }
+        let result = migration.execute(db, Direction::Down).await;
+        report_outcome(reporter, &name, Direction::Down, started, result)?;
 
-    if !ran {
-        println!("   No migrations have yet been run. Thus, none can be rolled back. ");
+        report.migrations.push(name);
     }
 
-    Ok(())
+    Ok(report)
 }
 
-fn rollback_success(name: &str) {
-    println!("    SUCCESS: {}", name);
+/// Computes the migrations [`rollback`] would revert, without changing the database.
+pub(crate) fn plan(
+    db: &mut impl DatabaseConnection,
+    definitions: &[Definition],
+    target: RollbackTarget,
+) -> Result<Vec<PlannedMigration>, MigrationError> {
+    if !db.migration_table_exists()? {
+        return Ok(Vec::new());
+    }
+
+    Ok(reverting(db.get_migrations()?, definitions, target)?
+        .into_iter()
+        .map(|migration| migration.plan(Direction::Down))
+        .collect())
 }
 
-fn rollback_failure(name: &str) {
-    println!("    FAILURE: {}", name);
+/// The applied migrations that `target` selects, in the order they should be reverted in.
+fn reverting<'a, 'b>(
+    mut ran_migrations: Vec<MigrationRecord>,
+    definitions: &'a [Definition<'b>],
+    target: RollbackTarget,
+) -> Result<Vec<&'a Definition<'b>>, MigrationError> {
+    // Most recently applied first, so that migrations are reverted in the reverse order they were
+    // applied in, even when an older version was applied after a newer one.
+    ran_migrations.sort_by_key(|migration| Reverse(migration.id));
+
+    let ids = ran_migrations
+        .into_iter()
+        .map(|migration| migration.name.parse::<MigrationId>());
+    let ids = match target {
+        RollbackTarget::Steps(steps) => ids.take(steps).collect(),
+        RollbackTarget::All => ids.collect(),
+        RollbackTarget::To(version) => {
+            let target = find_target(definitions, &version)?;
+
+            // The version only decides which migrations are newer than the target.
+            ids.filter(|id| id.as_ref().map_or(true, |id| id > target))
+                .collect::<Result<Vec<_>, MigrationError>>()
+        }
+    }?;
+
+    ids.into_iter()
+        .map(|id| {
+            definitions
+                .iter()
+                .find(|definition| definition.id == id)
+                .ok_or_else(|| MigrationError::MissingMigration(id.to_string()))
+        })
+        .collect()
 }
@@ -1,56 +1,123 @@
-use std::path::Path;
+use std::time::Instant;
 
-use chrono::Utc;
-
-use crate::MigrationError;
-use crate::{database::DatabaseConnection, io::MigrationDir};
+use super::verify_migrations::check_checksums;
+use super::{find_target, report_outcome, MigrationRecord, PlannedMigration};
+use crate::database::{AsyncDatabaseConnection, DatabaseConnection};
+use crate::io::MigrationSource;
+use crate::migration::Definition;
+use crate::runner::Runner;
+use crate::{Direction, MigrationError, MigrationEvent, MigrationReport, Migrator, Reporter};
 
 pub fn run_migrations(
     db: &mut impl DatabaseConnection,
-    path: &impl AsRef<Path>,
-) -> Result<(), MigrationError> {
-    if !db.migration_table_exists()? {
-        db.create_migrations_table()?;
-    }
+    source: impl Into<MigrationSource>,
+) -> Result<MigrationReport, MigrationError> {
+    Migrator::new(source).run(db)
+}
 
-    let ran_migrations: Vec<String> = db
-        .get_migrations()?
-        .iter()
-        .map(|migration| migration.name.clone())
-        .collect();
+/// Runs the outstanding migrations up to and including the migration named by `target`.
+pub fn run_migrations_to(
+    db: &mut impl DatabaseConnection,
+    source: impl Into<MigrationSource>,
+    target: &str,
+) -> Result<MigrationReport, MigrationError> {
+    Migrator::new(source).run_to(db, target)
+}
 
-    let migration_root_dir = MigrationDir::new(path);
+/// The async counterpart of [`run_migrations`]. Fails without running anything if a pending
+/// migration is written in Rust.
+#[cfg(feature = "async")]
+pub async fn run_migrations_async(
+    db: &mut impl AsyncDatabaseConnection,
+    source: impl Into<MigrationSource>,
+) -> Result<MigrationReport, MigrationError> {
+    Migrator::new(source).run_async(db).await
+}
 
-    let mut migration_dirs = migration_root_dir.get_migration_dir_names()?;
+/// The async counterpart of [`run_migrations_to`].
+#[cfg(feature = "async")]
+pub async fn run_migrations_to_async(
+    db: &mut impl AsyncDatabaseConnection,
+    source: impl Into<MigrationSource>,
+    target: &str,
+) -> Result<MigrationReport, MigrationError> {
+    Migrator::new(source).run_to_async(db, target).await
+}
 
-    let outstanding_migrations = migration_dirs
-        .iter_mut()
-        .filter(|dir_name| !ran_migrations.contains(dir_name));
+pub(crate) async fn run(
+    db: &mut impl Runner,
+    definitions: &[Definition<'_>],
+    target: Option<&str>,
+    reporter: &(impl Reporter + ?Sized),
+) -> Result<MigrationReport, MigrationError> {
+    let mut report = MigrationReport::new(Direction::Up);
 
-    for migration in outstanding_migrations {
-        let path = format!("{}/up.sql", migration);
-        let up_sql = migration_root_dir.get_file_contents(&path)?;
+    let connection = db.connection();
+    if connection.migration_table_exists().await? {
+        connection.upgrade_migrations_table().await?;
+    } else {
+        connection.create_migrations_table().await?;
+    }
 
-        match db.execute_sql(&up_sql) {
-            Ok(()) => migration_success(migration),
-            Err(err) => {
-                migration_failure(migration);
-                return Err(err.into());
-            }
-        }
+    let migrations = connection.get_migrations().await?;
+    let pending = pending(&migrations, definitions, target)?;
+    db.check(&pending)?;
 
-        let now = Utc::now().naive_utc();
+    for migration in pending {
+        let name = migration.name();
 
-        db.create_migration(migration, now)?;
+        reporter.report(&MigrationEvent::Started {
+            name: &name,
+            direction: Direction::Up,
+        });
+        let started = Instant::now();
+
+        let result = migration.execute(db, Direction::Up).await;
+        report_outcome(reporter, &name, Direction::Up, started, result)?;
+
+        report.migrations.push(name);
     }
 
-    Ok(())
+    Ok(report)
 }
 
-fn migration_success(name: &str) {
-    println!("    SUCCESS: {}", name);
+/// Computes the migrations [`run`] would apply, without changing the database.
+pub(crate) fn plan(
+    db: &mut impl DatabaseConnection,
+    definitions: &[Definition],
+    target: Option<&str>,
+) -> Result<Vec<PlannedMigration>, MigrationError> {
+    let migrations = if db.migration_table_exists()? {
+        db.get_migrations()?
+    } else {
+        Vec::new()
+    };
+
+    Ok(pending(&migrations, definitions, target)?
+        .into_iter()
+        .map(|migration| migration.plan(Direction::Up))
+        .collect())
 }
 
-fn migration_failure(name: &str) {
-    println!("    FAILURE: {}", name);
+/// The migrations that have not been run yet, up to and including `target`, in the order they
+/// should be run in.
+fn pending<'a, 'b>(
+    migrations: &[MigrationRecord],
+    definitions: &'a [Definition<'b>],
+    target: Option<&str>,
+) -> Result<Vec<&'a Definition<'b>>, MigrationError> {
+    check_checksums(migrations, definitions)?;
+
+    let ran_migrations: Vec<&String> = migrations.iter().map(|migration| &migration.name).collect();
+
+    let last = match target {
+        Some(target) => Some(find_target(definitions, target)?),
+        None => None,
+    };
+
+    Ok(definitions
+        .iter()
+        .filter(|definition| last.is_none_or(|last| definition.id <= *last))
+        .filter(|definition| !ran_migrations.contains(&&definition.name()))
+        .collect())
 }
@@ -0,0 +1,58 @@
+use crate::commands::MigrationRecord;
+use crate::database::DatabaseConnection;
+use crate::io::MigrationSource;
+use crate::migration::Definition;
+use crate::{MigrationError, Migrator};
+
+/// Checks that none of the applied migrations have been changed on disk since they were run.
+///
+/// Migrations that were run before dmt started recording checksums, Rust migrations, and
+/// migrations that no longer exist are not checked.
+pub fn verify_migrations(
+    db: &mut impl DatabaseConnection,
+    source: impl Into<MigrationSource>,
+) -> Result<(), MigrationError> {
+    Migrator::new(source).verify(db)
+}
+
+pub(crate) fn verify(
+    db: &mut impl DatabaseConnection,
+    definitions: &[Definition],
+) -> Result<(), MigrationError> {
+    if !db.migration_table_exists()? {
+        return Ok(());
+    }
+
+    let migrations = db.get_migrations()?;
+
+    check_checksums(&migrations, definitions)
+}
+
+pub(super) fn check_checksums(
+    migrations: &[MigrationRecord],
+    definitions: &[Definition],
+) -> Result<(), MigrationError> {
+    let mut drifted = Vec::new();
+
+    for migration in migrations {
+        let Some(checksum) = &migration.checksum else {
+            continue;
+        };
+
+        let current = definitions
+            .iter()
+            .find(|definition| definition.name() == migration.name)
+            .and_then(|definition| definition.checksum());
+
+        if current.is_some_and(|current| current != *checksum) {
+            drifted.push(migration.name.clone());
+        }
+    }
+
+    if drifted.is_empty() {
+        Ok(())
+    } else {
+        drifted.sort();
+        Err(MigrationError::ChecksumMismatch(drifted))
+    }
+}
@@ -1,31 +1,213 @@
+mod mysql;
 mod postgres;
+mod retry;
+mod sqlite;
 mod turso;
 
+use std::future::Future;
+use std::thread;
+
 use chrono::NaiveDateTime;
+use tokio::runtime::{self, Runtime};
 
+use self::mysql::MysqlConnection;
+#[cfg(feature = "async")]
+use self::postgres::AsyncPostgresConnection;
 use self::postgres::PostgresConnection;
+use self::retry::{is_transient, Backoff};
+use self::sqlite::SqliteConnection;
+#[cfg(feature = "async")]
+use self::turso::AsyncTursoConnection;
 use self::turso::TursoConnection;
 
-use crate::commands::Migration;
-use crate::{ConfigError, ConnectionError, Database, DmtConfig, DmtError, MigrationError};
+use crate::commands::MigrationRecord;
+use crate::config::DEFAULT_TABLE;
+use crate::{
+    ConfigError, ConnectionError, Database, DmtConfig, DmtError, HumanReporter, MigrationConfig,
+    MigrationError, MigrationEvent, Reporter,
+};
+
+/// The table the applied migrations are recorded in, and the schema it is in, if not the default
+/// one of the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationTable {
+    pub(crate) schema: Option<String>,
+    pub(crate) name: String,
+}
+
+impl MigrationTable {
+    pub(crate) fn new(config: &MigrationConfig) -> Self {
+        Self {
+            schema: config.schema.clone(),
+            name: config.table.clone(),
+        }
+    }
+
+    /// Whether this is the `migration` table of the connection's default schema, whose lock is the
+    /// one every version of dmt takes.
+    pub(crate) fn is_default(&self) -> bool {
+        self.schema.is_none() && self.name == DEFAULT_TABLE
+    }
+
+    /// The table named `name` in the same schema, such as the table of a lock that belongs to it.
+    pub(crate) fn sibling(&self, name: &str) -> Self {
+        Self {
+            schema: self.schema.clone(),
+            name: name.to_string(),
+        }
+    }
+
+    /// The name of the table, qualified with its schema, for use in SQL that quotes identifiers
+    /// with `quote`.
+    pub(crate) fn qualified(&self, quote: char) -> String {
+        match &self.schema {
+            Some(schema) => format!(
+                "{}.{}",
+                quote_identifier(schema, quote),
+                quote_identifier(&self.name, quote)
+            ),
+            None => quote_identifier(&self.name, quote),
+        }
+    }
+}
+
+/// The runtime a blocking connection runs the futures of its async counterpart on.
+pub(crate) fn runtime() -> Result<Runtime, ConnectionError> {
+    runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(ConnectionError::RuntimeError)
+}
+
+/// Surrounds an identifier with `quote`, doubling the quotes inside it, so that any name can be
+/// used in SQL.
+pub(crate) fn quote_identifier(identifier: &str, quote: char) -> String {
+    let escaped = identifier.replace(quote, &format!("{quote}{quote}"));
+
+    format!("{quote}{escaped}{quote}")
+}
 
 pub trait DatabaseConnection {
     fn create_migrations_table(&mut self) -> Result<(), ConnectionError>;
+    /// Adds any columns missing from a migration table created by an older version of dmt.
+    fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError>;
     fn migration_table_exists(&mut self) -> Result<bool, ConnectionError>;
-    fn get_migrations(&mut self) -> Result<Vec<Migration>, ConnectionError>;
+    /// Reads the applied migrations. Those of a table that has not been upgraded yet have no
+    /// checksum.
+    fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError>;
+    /// Records a migration as run. Migrations written in Rust have no checksum.
     fn create_migration(
         &mut self,
         name: &str,
         time: NaiveDateTime,
-    ) -> Result<Migration, ConnectionError>;
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError>;
     fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError>;
+    /// Runs `sql` one statement at a time, outside of any transaction, for statements such as
+    /// `CREATE INDEX CONCURRENTLY` that cannot run inside one.
+    fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError>;
+    /// Runs a query and returns its rows, with every value as text and `NULL`s as `None`.
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError>;
+    /// Runs a migration's `up.sql` and records it, committing both or neither.
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError>;
+    /// Runs a migration's `down.sql` and removes its record, committing both or neither.
+    fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError>;
     fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError>;
     fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError>;
+    /// Tries to take the migration lock without waiting, returning whether it was taken.
+    fn try_lock(&mut self) -> Result<bool, ConnectionError>;
+    /// Releases the migration lock taken by this connection.
+    fn unlock(&mut self) -> Result<(), ConnectionError>;
+    /// Releases the migration lock, even if it is held by another connection. On Postgres and
+    /// MySQL this ends the session that holds it, interrupting any migration it is running.
+    fn force_unlock(&mut self) -> Result<(), ConnectionError>;
+}
+
+/// The async counterpart of [`DatabaseConnection`], for applications that run inside a tokio
+/// runtime, where blocking on a query would stall a worker thread.
+///
+/// The blocking Postgres, Turso and SQLite connections are thin wrappers that run this trait's
+/// futures to completion on a runtime of their own.
+pub trait AsyncDatabaseConnection {
+    fn create_migrations_table(
+        &mut self,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    /// Adds any columns missing from a migration table created by an older version of dmt.
+    fn upgrade_migrations_table(
+        &mut self,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    fn migration_table_exists(
+        &mut self,
+    ) -> impl Future<Output = Result<bool, ConnectionError>> + Send;
+    /// Reads the applied migrations. Those of a table that has not been upgraded yet have no
+    /// checksum.
+    fn get_migrations(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<MigrationRecord>, ConnectionError>> + Send;
+    /// Records a migration as run. Migrations written in Rust have no checksum.
+    fn create_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> impl Future<Output = Result<MigrationRecord, ConnectionError>> + Send;
+    fn execute_sql(
+        &mut self,
+        sql: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    /// Runs `sql` one statement at a time, outside of any transaction, for statements such as
+    /// `CREATE INDEX CONCURRENTLY` that cannot run inside one.
+    fn execute_sql_without_transaction(
+        &mut self,
+        sql: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    /// Runs a query and returns its rows, with every value as text and `NULL`s as `None`.
+    fn query(
+        &mut self,
+        sql: &str,
+    ) -> impl Future<Output = Result<Vec<Vec<Option<String>>>, ConnectionError>> + Send;
+    /// Runs a migration's `up.sql` and records it, committing both or neither.
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> impl Future<Output = Result<MigrationRecord, ConnectionError>> + Send;
+    /// Runs a migration's `down.sql` and removes its record, committing both or neither.
+    fn revert_migration(
+        &mut self,
+        name: &str,
+        sql: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    fn remove_migration_by_id(
+        &mut self,
+        id: i32,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    fn remove_migration_by_name(
+        &mut self,
+        name: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    /// Tries to take the migration lock without waiting, returning whether it was taken.
+    fn try_lock(&mut self) -> impl Future<Output = Result<bool, ConnectionError>> + Send;
+    /// Releases the migration lock taken by this connection.
+    fn unlock(&mut self) -> impl Future<Output = Result<(), ConnectionError>> + Send;
+    /// Releases the migration lock, even if it is held by another connection. On Postgres and
+    /// MySQL this ends the session that holds it, interrupting any migration it is running.
+    fn force_unlock(&mut self) -> impl Future<Output = Result<(), ConnectionError>> + Send;
 }
 
 pub enum MigrationDatabase {
     Turso(Box<TursoConnection>),
     Postgres(Box<PostgresConnection>),
+    Sqlite(Box<SqliteConnection>),
+    Mysql(Box<MysqlConnection>),
 }
 
 impl MigrationDatabase {
@@ -33,20 +215,49 @@ impl MigrationDatabase {
         match self {
             Self::Turso(conn) => conn.as_mut(),
             Self::Postgres(conn) => conn.as_mut(),
+            Self::Sqlite(conn) => conn.as_mut(),
+            Self::Mysql(conn) => conn.as_mut(),
         }
     }
-}
 
-impl TryFrom<&DmtConfig> for MigrationDatabase {
-    type Error = DmtError;
+    /// Connects to the database of `config`, retrying as set in its `connection.retry`. Every
+    /// failed attempt that is retried is sent to `reporter`.
+    pub fn connect_with_reporter(
+        config: &DmtConfig,
+        reporter: &dyn Reporter,
+    ) -> Result<Self, DmtError> {
+        let mut backoff = Backoff::new(config.connection.retry.as_ref())?;
+
+        loop {
+            match Self::open(config) {
+                Err(DmtError::MigrationError(MigrationError::ConnectionError(error)))
+                    if is_transient(&error) =>
+                {
+                    let Some(delay) = backoff.next_delay() else {
+                        return Err(MigrationError::ConnectionError(error).into());
+                    };
+
+                    reporter.report(&MigrationEvent::ConnectionFailed {
+                        attempt: backoff.attempts(),
+                        delay,
+                        error: &error,
+                    });
+                    thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn open(config: &DmtConfig) -> Result<Self, DmtError> {
+        let table = MigrationTable::new(&config.migration);
 
-    fn try_from(config: &DmtConfig) -> Result<Self, Self::Error> {
         match config.connection.database {
             Some(Database::Postgres) => {
                 if let Some(config) = &config.connection.postgres {
-                    Ok(Self::Postgres(Box::new(
-                        PostgresConnection::new(config).map_err(MigrationError::ConnectionError)?,
-                    )))
+                    Ok(Self::Postgres(Box::new(PostgresConnection::new(
+                        config, table,
+                    )?)))
                 } else {
                     Err(DmtError::ConfigError(ConfigError::ParseError(
                         "No postgres config found".to_string(),
@@ -56,11 +267,36 @@ impl TryFrom<&DmtConfig> for MigrationDatabase {
             Some(Database::Turso) => {
                 if let Some(config) = &config.connection.turso {
                     Ok(Self::Turso(Box::new(
-                        TursoConnection::new(config).map_err(MigrationError::ConnectionError)?,
+                        TursoConnection::new(config, table)
+                            .map_err(MigrationError::ConnectionError)?,
                     )))
                 } else {
                     Err(DmtError::ConfigError(ConfigError::ParseError(
-                        "No postgres config found".to_string(),
+                        "No turso config found".to_string(),
+                    )))
+                }
+            }
+            Some(Database::Sqlite) => {
+                if let Some(config) = &config.connection.sqlite {
+                    Ok(Self::Sqlite(Box::new(
+                        SqliteConnection::new(config, table)
+                            .map_err(MigrationError::ConnectionError)?,
+                    )))
+                } else {
+                    Err(DmtError::ConfigError(ConfigError::ParseError(
+                        "No sqlite config found".to_string(),
+                    )))
+                }
+            }
+            Some(Database::Mysql) => {
+                if let Some(config) = &config.connection.mysql {
+                    Ok(Self::Mysql(Box::new(
+                        MysqlConnection::new(config, table)
+                            .map_err(MigrationError::ConnectionError)?,
+                    )))
+                } else {
+                    Err(DmtError::ConfigError(ConfigError::ParseError(
+                        "No mysql config found".to_string(),
                     )))
                 }
             }
@@ -71,16 +307,29 @@ impl TryFrom<&DmtConfig> for MigrationDatabase {
     }
 }
 
+impl TryFrom<&DmtConfig> for MigrationDatabase {
+    type Error = DmtError;
+
+    /// Connects to the database of `config`, printing any retries for people to read.
+    fn try_from(config: &DmtConfig) -> Result<Self, Self::Error> {
+        Self::connect_with_reporter(config, &HumanReporter)
+    }
+}
+
 impl DatabaseConnection for MigrationDatabase {
     fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
         self.connection().create_migrations_table()
     }
 
+    fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        self.connection().upgrade_migrations_table()
+    }
+
     fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
         self.connection().migration_table_exists()
     }
 
-    fn get_migrations(&mut self) -> Result<Vec<Migration>, ConnectionError> {
+    fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
         self.connection().get_migrations()
     }
 
@@ -88,14 +337,37 @@ impl DatabaseConnection for MigrationDatabase {
         &mut self,
         name: &str,
         time: NaiveDateTime,
-    ) -> Result<Migration, ConnectionError> {
-        self.connection().create_migration(name, time)
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.connection().create_migration(name, time, checksum)
     }
 
     fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
         self.connection().execute_sql(sql)
     }
 
+    fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        self.connection().execute_sql_without_transaction(sql)
+    }
+
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        self.connection().query(sql)
+    }
+
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.connection().apply_migration(name, time, checksum, sql)
+    }
+
+    fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        self.connection().revert_migration(name, sql)
+    }
+
     fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
         self.connection().remove_migration_by_id(id)
     }
@@ -103,4 +375,234 @@ impl DatabaseConnection for MigrationDatabase {
     fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
         self.connection().remove_migration_by_name(name)
     }
+
+    fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        self.connection().try_lock()
+    }
+
+    fn unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection().unlock()
+    }
+
+    fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection().force_unlock()
+    }
+}
+
+/// The async counterpart of [`MigrationDatabase`], for Postgres, Turso and SQLite databases.
+#[cfg(feature = "async")]
+pub enum AsyncMigrationDatabase {
+    /// A Turso database, or a SQLite database opened through libSQL.
+    Turso(Box<AsyncTursoConnection>),
+    Postgres(Box<AsyncPostgresConnection>),
+}
+
+#[cfg(feature = "async")]
+impl AsyncMigrationDatabase {
+    /// Connects to the database of `config`, printing any retries for people to read. MySQL has
+    /// no async connection, so a MySQL config is an error.
+    pub async fn connect(config: &DmtConfig) -> Result<Self, DmtError> {
+        Self::connect_with_reporter(config, &HumanReporter).await
+    }
+
+    /// The async counterpart of [`MigrationDatabase::connect_with_reporter`], which waits between
+    /// attempts without blocking the runtime. The reporter is `Sync` so that connecting can be
+    /// spawned onto another thread.
+    pub async fn connect_with_reporter(
+        config: &DmtConfig,
+        reporter: &(dyn Reporter + Sync),
+    ) -> Result<Self, DmtError> {
+        let mut backoff = Backoff::new(config.connection.retry.as_ref())?;
+
+        loop {
+            match Self::open(config).await {
+                Err(DmtError::MigrationError(MigrationError::ConnectionError(error)))
+                    if is_transient(&error) =>
+                {
+                    let Some(delay) = backoff.next_delay() else {
+                        return Err(MigrationError::ConnectionError(error).into());
+                    };
+
+                    reporter.report(&MigrationEvent::ConnectionFailed {
+                        attempt: backoff.attempts(),
+                        delay,
+                        error: &error,
+                    });
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn open(config: &DmtConfig) -> Result<Self, DmtError> {
+        let missing = |database: &str| {
+            DmtError::ConfigError(ConfigError::ParseError(format!(
+                "No {database} config found"
+            )))
+        };
+        let table = MigrationTable::new(&config.migration);
+
+        match config.connection.database {
+            Some(Database::Postgres) => {
+                let config = config
+                    .connection
+                    .postgres
+                    .as_ref()
+                    .ok_or_else(|| missing("postgres"))?;
+                let connection = AsyncPostgresConnection::new(config, table).await?;
+
+                Ok(Self::Postgres(Box::new(connection)))
+            }
+            Some(Database::Turso) => {
+                let config = config
+                    .connection
+                    .turso
+                    .as_ref()
+                    .ok_or_else(|| missing("turso"))?;
+                let connection = AsyncTursoConnection::new(config, table)
+                    .await
+                    .map_err(MigrationError::ConnectionError)?;
+
+                Ok(Self::Turso(Box::new(connection)))
+            }
+            Some(Database::Sqlite) => {
+                let config = config
+                    .connection
+                    .sqlite
+                    .as_ref()
+                    .ok_or_else(|| missing("sqlite"))?;
+                let connection = AsyncTursoConnection::local(&config.path, table)
+                    .await
+                    .map_err(MigrationError::ConnectionError)?;
+
+                Ok(Self::Turso(Box::new(connection)))
+            }
+            Some(Database::Mysql) => Err(DmtError::ConfigError(ConfigError::ParseError(
+                "MySQL is not supported by the async API".to_string(),
+            ))),
+            None => Err(DmtError::ConfigError(ConfigError::ParseError(
+                "Database type not specified in config".to_string(),
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncDatabaseConnection for AsyncMigrationDatabase {
+    async fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.create_migrations_table().await,
+            Self::Postgres(conn) => conn.create_migrations_table().await,
+        }
+    }
+
+    async fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.upgrade_migrations_table().await,
+            Self::Postgres(conn) => conn.upgrade_migrations_table().await,
+        }
+    }
+
+    async fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.migration_table_exists().await,
+            Self::Postgres(conn) => conn.migration_table_exists().await,
+        }
+    }
+
+    async fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.get_migrations().await,
+            Self::Postgres(conn) => conn.get_migrations().await,
+        }
+    }
+
+    async fn create_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.create_migration(name, time, checksum).await,
+            Self::Postgres(conn) => conn.create_migration(name, time, checksum).await,
+        }
+    }
+
+    async fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.execute_sql(sql).await,
+            Self::Postgres(conn) => conn.execute_sql(sql).await,
+        }
+    }
+
+    async fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.execute_sql_without_transaction(sql).await,
+            Self::Postgres(conn) => conn.execute_sql_without_transaction(sql).await,
+        }
+    }
+
+    async fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.query(sql).await,
+            Self::Postgres(conn) => conn.query(sql).await,
+        }
+    }
+
+    async fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.apply_migration(name, time, checksum, sql).await,
+            Self::Postgres(conn) => conn.apply_migration(name, time, checksum, sql).await,
+        }
+    }
+
+    async fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.revert_migration(name, sql).await,
+            Self::Postgres(conn) => conn.revert_migration(name, sql).await,
+        }
+    }
+
+    async fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.remove_migration_by_id(id).await,
+            Self::Postgres(conn) => conn.remove_migration_by_id(id).await,
+        }
+    }
+
+    async fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.remove_migration_by_name(name).await,
+            Self::Postgres(conn) => conn.remove_migration_by_name(name).await,
+        }
+    }
+
+    async fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.try_lock().await,
+            Self::Postgres(conn) => conn.try_lock().await,
+        }
+    }
+
+    async fn unlock(&mut self) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.unlock().await,
+            Self::Postgres(conn) => conn.unlock().await,
+        }
+    }
+
+    async fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        match self {
+            Self::Turso(conn) => conn.force_unlock().await,
+            Self::Postgres(conn) => conn.force_unlock().await,
+        }
+    }
 }
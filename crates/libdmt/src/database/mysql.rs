@@ -0,0 +1,340 @@
+use chrono::NaiveDateTime;
+use mysql::prelude::Queryable;
+use mysql::{Conn, Opts, TxOpts, Value};
+
+use crate::commands::MigrationRecord;
+use crate::config::MysqlConfig;
+use crate::ConnectionError;
+
+use super::{quote_identifier, DatabaseConnection, MigrationTable};
+
+/// The name of the user level lock held while migrating, made from `<schema>.<table>`. User level
+/// locks are shared by the whole server, so the name keeps the migrations of different databases
+/// and tables from waiting on, or unlocking, each other. It is hashed because lock names may be
+/// at most 64 characters long, and schema and table names can each be as long as that.
+const LOCK_NAME_SQL: &str = r#"
+    SELECT CONCAT('dmt_', SHA1(CONCAT(COALESCE(?, DATABASE(), ''), '.', ?)))
+"#;
+
+/// The statements that use the migration table, whose name comes from the config.
+struct Queries {
+    table: MigrationTable,
+    create_schema: Option<String>,
+    create_table: String,
+    add_checksum: String,
+    get_migrations: String,
+    get_legacy_migrations: String,
+    insert_migration: String,
+    delete_migration_by_name: String,
+    delete_migration_by_id: String,
+}
+
+impl Queries {
+    fn new(table: MigrationTable) -> Self {
+        let name = table.qualified('`');
+
+        Self {
+            create_schema: table.schema.as_ref().map(|schema| {
+                format!(
+                    "CREATE SCHEMA IF NOT EXISTS {}",
+                    quote_identifier(schema, '`')
+                )
+            }),
+            create_table: format!(
+                r#"
+        CREATE TABLE IF NOT EXISTS {name} (
+            id INT AUTO_INCREMENT PRIMARY KEY NOT NULL,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            time DATETIME(6) NOT NULL,
+            checksum VARCHAR(64)
+        );
+    "#
+            ),
+            add_checksum: format!("ALTER TABLE {name} ADD COLUMN checksum VARCHAR(64);"),
+            get_migrations: format!(
+                r#"
+        SELECT id, name, time, checksum FROM {name}
+    "#
+            ),
+            get_legacy_migrations: format!(
+                r#"
+        SELECT id, name, time, NULL AS checksum FROM {name}
+    "#
+            ),
+            insert_migration: format!(
+                r#"
+    INSERT INTO {name} (name, time, checksum) VALUES (?, ?, ?)
+"#
+            ),
+            delete_migration_by_name: format!(
+                r#"
+    DELETE FROM {name} WHERE name = ?
+"#
+            ),
+            delete_migration_by_id: format!(
+                r#"
+       DELETE FROM {name} WHERE id = ?
+    "#
+            ),
+            table,
+        }
+    }
+}
+
+/// A MySQL or MariaDB database.
+///
+/// Unlike Postgres, MySQL implicitly commits every DDL statement (`CREATE`, `ALTER`, `DROP`, ...),
+/// so although migrations are still run inside a transaction, a failing `up.sql` or `down.sql`
+/// can leave the schema partially changed and has to be cleaned up by hand.
+pub struct MysqlConnection {
+    connection: Conn,
+    queries: Queries,
+    lock_name: String,
+}
+
+impl MysqlConnection {
+    pub fn new(config: &MysqlConfig, table: MigrationTable) -> Result<Self, ConnectionError> {
+        let opts = Opts::from_url(&config.connection_string).map_err(mysql::Error::from)?;
+        let mut connection = Conn::new(opts)?;
+
+        let lock_name = connection
+            .exec_first(LOCK_NAME_SQL, (&table.schema, &table.name))?
+            .unwrap_or_default();
+
+        Ok(Self {
+            connection,
+            queries: Queries::new(table),
+            lock_name,
+        })
+    }
+
+    /// Whether the migration table has a `checksum` column, which tables created before checksums
+    /// were recorded only get once they are upgraded.
+    fn has_checksum(&mut self) -> Result<bool, ConnectionError> {
+        let sql = r#"
+        SELECT 1 FROM information_schema.columns
+            WHERE table_schema = COALESCE(?, DATABASE()) AND table_name = ?
+                AND column_name = 'checksum'
+    "#;
+
+        let table = &self.queries.table;
+        let res: Option<i32> = self
+            .connection
+            .exec_first(sql, (table.schema.as_deref(), table.name.as_str()))?;
+
+        Ok(res.is_some())
+    }
+}
+
+impl DatabaseConnection for MysqlConnection {
+    fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        if let Some(create_schema) = &self.queries.create_schema {
+            self.connection.query_drop(create_schema)?;
+        }
+        self.connection.query_drop(&self.queries.create_table)?;
+
+        Ok(())
+    }
+
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        // Text protocol queries return every value as bytes, whatever its type.
+        let rows = self
+            .connection
+            .query::<mysql::Row, _>(sql)?
+            .into_iter()
+            .map(|row| {
+                row.unwrap()
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::NULL => None,
+                        Value::Bytes(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+                        value => Some(value.as_sql(true)),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(rows)
+    }
+
+    fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        // Only MariaDB supports `ADD COLUMN IF NOT EXISTS`, so the columns are looked up first.
+        if !self.has_checksum()? {
+            self.connection.query_drop(&self.queries.add_checksum)?;
+        }
+
+        Ok(())
+    }
+
+    fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
+        let sql = r#"
+        SELECT 1 AS `exists` FROM information_schema.tables
+            WHERE table_schema = COALESCE(?, DATABASE()) AND table_name = ?
+    "#;
+
+        let table = &self.queries.table;
+        let res: Option<i32> = self
+            .connection
+            .exec_first(sql, (table.schema.as_deref(), table.name.as_str()))?;
+
+        Ok(res.is_some())
+    }
+
+    fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
+        let sql = if self.has_checksum()? {
+            &self.queries.get_migrations
+        } else {
+            &self.queries.get_legacy_migrations
+        };
+        let migrations = self
+            .connection
+            .query_map(sql, |(id, name, time, checksum)| MigrationRecord {
+                id,
+                name,
+                time,
+                checksum,
+            })?;
+
+        Ok(migrations)
+    }
+
+    fn create_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        // MySQL has no `RETURNING`, so the generated id is read back from the connection instead.
+        self.connection
+            .exec_drop(&self.queries.insert_migration, (name, time, checksum))?;
+
+        Ok(MigrationRecord {
+            id: self.connection.last_insert_id() as i32,
+            name: name.to_string(),
+            time,
+            checksum: checksum.map(str::to_string),
+        })
+    }
+
+    fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        let mut transaction = self.connection.start_transaction(TxOpts::default())?;
+
+        transaction
+            .query_drop(sql)
+            .map_err(ConnectionError::PartiallyApplied)?;
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        self.connection.query_drop(sql)?;
+
+        Ok(())
+    }
+
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        // Any DDL in `sql` implicitly commits, so only the data changes and the `migration` row
+        // are guaranteed to be committed together.
+        let mut transaction = self.connection.start_transaction(TxOpts::default())?;
+
+        transaction
+            .query_drop(sql)
+            .map_err(ConnectionError::PartiallyApplied)?;
+
+        transaction.exec_drop(&self.queries.insert_migration, (name, time, checksum))?;
+        let id = transaction.last_insert_id().unwrap_or_default();
+
+        transaction.commit()?;
+
+        Ok(MigrationRecord {
+            id: id as i32,
+            name: name.to_string(),
+            time,
+            checksum: Some(checksum.to_string()),
+        })
+    }
+
+    fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        let mut transaction = self.connection.start_transaction(TxOpts::default())?;
+
+        transaction
+            .query_drop(sql)
+            .map_err(ConnectionError::PartiallyApplied)?;
+
+        transaction.exec_drop(&self.queries.delete_migration_by_name, (name,))?;
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
+        self.connection
+            .exec_drop(&self.queries.delete_migration_by_id, (id,))?;
+
+        Ok(())
+    }
+
+    fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
+        self.connection
+            .exec_drop(&self.queries.delete_migration_by_name, (name,))?;
+
+        Ok(())
+    }
+
+    fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        let sql = r#"
+        SELECT GET_LOCK(?, 0)
+    "#;
+
+        // `GET_LOCK` returns NULL, rather than 0, when taking the lock fails with an error.
+        let locked: Option<Option<i32>> = self.connection.exec_first(sql, (&self.lock_name,))?;
+
+        match locked {
+            Some(Some(locked)) => Ok(locked == 1),
+            _ => Err(ConnectionError::LockError(format!(
+                "MySQL failed to take the migration lock `{}`",
+                self.lock_name
+            ))),
+        }
+    }
+
+    fn unlock(&mut self) -> Result<(), ConnectionError> {
+        let sql = r#"
+        SELECT RELEASE_LOCK(?)
+    "#;
+
+        self.connection.exec_drop(sql, (&self.lock_name,))?;
+
+        Ok(())
+    }
+
+    fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        // User level locks belong to the session that took them, so the only way to release
+        // another session's lock is to end that session.
+        let sql = r#"
+        SELECT IS_USED_LOCK(?), CONNECTION_ID()
+    "#;
+
+        let holder: Option<(Option<u64>, u64)> =
+            self.connection.exec_first(sql, (&self.lock_name,))?;
+
+        match holder {
+            Some((Some(holder), current)) if holder != current => {
+                self.connection.query_drop(format!("KILL {holder}"))?
+            }
+            Some((Some(_), _)) => self.unlock()?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
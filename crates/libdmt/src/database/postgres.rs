@@ -1,125 +1,956 @@
+use std::fs;
+use std::time::Duration;
+
 use chrono::NaiveDateTime;
-use postgres::{Client, NoTls, Row};
+#[cfg(feature = "native-tls")]
+use postgres_native_tls::MakeTlsConnector;
+use tokio::runtime::Runtime;
+use tokio_postgres::config::SslMode;
+#[cfg(not(feature = "native-tls"))]
+use tokio_postgres::NoTls;
+use tokio_postgres::{Client, Row, SimpleQueryMessage};
+
+use crate::commands::MigrationRecord;
+use crate::config::{PostgresConfig, PostgresTlsConfig, TlsMode};
+use crate::{ConfigError, ConnectionError, DmtError, MigrationError};
+
+use super::{
+    quote_identifier, runtime, AsyncDatabaseConnection, DatabaseConnection, MigrationTable,
+};
+
+/// What connections are made with, which can only use TLS with the `native-tls` feature.
+#[cfg(feature = "native-tls")]
+type Tls = MakeTlsConnector;
+#[cfg(not(feature = "native-tls"))]
+type Tls = NoTls;
+
+/// The key of the session level advisory lock held while migrating the default table, "dmt" in
+/// ASCII.
+const LOCK_KEY: i64 = 0x646d74;
+
+/// The key of the lock of any other table, so that applications that keep their migrations in
+/// different tables of one database do not wait on each other.
+const LOCK_KEY_SQL: &str = r#"
+    SELECT hashtext(COALESCE($1::text, current_schema()) || '.' || $2::text)::bigint
+"#;
+
+/// Looks the table up in the schema it is created in when none is given, which is the first
+/// schema of the search path that exists.
+const MIGRATION_TABLE_EXISTS_SQL: &str = r#"
+    SELECT 1 AS "exists" FROM information_schema."tables"
+        WHERE "table_schema" = COALESCE($1::text, current_schema()) AND "table_name" = $2::text
+"#;
+
+const CHECKSUM_EXISTS_SQL: &str = r#"
+    SELECT 1 FROM information_schema."columns"
+        WHERE "table_schema" = COALESCE($1::text, current_schema()) AND "table_name" = $2::text
+            AND "column_name" = 'checksum'
+"#;
 
-use crate::commands::Migration;
-use crate::config::PostgresConfig;
-use crate::ConnectionError;
+const TRY_LOCK_SQL: &str = r#"
+    SELECT pg_try_advisory_lock($1)
+"#;
 
-use super::DatabaseConnection;
+const UNLOCK_SQL: &str = r#"
+    SELECT pg_advisory_unlock($1)
+"#;
 
-impl From<Row> for Migration {
+/// Advisory locks belong to the session that took them, so the only way to release another
+/// session's lock is to end that session.
+const FORCE_UNLOCK_SQL: &str = r#"
+    SELECT pg_terminate_backend(pid) FROM pg_locks
+        WHERE locktype = 'advisory'
+            AND ((classid::bigint << 32) | objid::bigint) = $1
+            AND pid <> pg_backend_pid()
+"#;
+
+impl From<Row> for MigrationRecord {
     fn from(row: Row) -> Self {
-        Migration {
+        MigrationRecord {
             id: row.get(0),
             name: row.get(1),
             time: row.get(2),
+            checksum: row.get(3),
         }
     }
 }
 
-impl From<&Row> for Migration {
+impl From<&Row> for MigrationRecord {
     fn from(row: &Row) -> Self {
-        Migration {
+        MigrationRecord {
             id: row.get(0),
             name: row.get(1),
             time: row.get(2),
+            checksum: row.get(3),
         }
     }
 }
 
+/// The statements that use the migration table, whose name comes from the config.
+struct Queries {
+    table: MigrationTable,
+    create_schema: Option<String>,
+    create_table: String,
+    upgrade_table: String,
+    get_migrations: String,
+    get_legacy_migrations: String,
+    insert_migration: String,
+    delete_migration_by_name: String,
+    delete_migration_by_id: String,
+}
+
+impl Queries {
+    fn new(table: MigrationTable) -> Self {
+        let name = table.qualified('"');
+
+        Self {
+            create_schema: table.schema.as_ref().map(|schema| {
+                format!(
+                    "CREATE SCHEMA IF NOT EXISTS {}",
+                    quote_identifier(schema, '"')
+                )
+            }),
+            create_table: format!(
+                r#"
+    CREATE TABLE IF NOT EXISTS {name} (
+        id SERIAL PRIMARY KEY NOT NULL,
+        name VARCHAR(255) UNIQUE NOT NULL,
+        time TIMESTAMP NOT NULL,
+        checksum VARCHAR(64)
+    );
+"#
+            ),
+            upgrade_table: format!(
+                r#"
+    ALTER TABLE {name} ADD COLUMN IF NOT EXISTS checksum VARCHAR(64);
+"#
+            ),
+            get_migrations: format!(
+                r#"
+    SELECT id, name, time, checksum FROM {name}
+"#
+            ),
+            get_legacy_migrations: format!(
+                r#"
+    SELECT id, name, time, NULL::varchar AS checksum FROM {name}
+"#
+            ),
+            insert_migration: format!(
+                r#"
+    INSERT INTO {name} (id, name, time, checksum) VALUES (DEFAULT, $1, $2, $3)
+        RETURNING id, name, time, checksum;
+"#
+            ),
+            delete_migration_by_name: format!(
+                r#"
+    DELETE FROM {name} WHERE name = $1
+"#
+            ),
+            delete_migration_by_id: format!(
+                r#"
+    DELETE FROM {name} WHERE id = $1
+"#
+            ),
+            table,
+        }
+    }
+}
+
+/// A Postgres database, driven by a current thread runtime of its own.
 pub struct PostgresConnection {
-    connection: Client,
+    runtime: Runtime,
+    connection: AsyncPostgresConnection,
 }
 
 impl PostgresConnection {
-    pub fn new(config: &PostgresConfig) -> Result<Self, ConnectionError> {
-        let postgres_client = Client::connect(&config.connection_string, NoTls)?;
+    pub fn new(config: &PostgresConfig, table: MigrationTable) -> Result<Self, DmtError> {
+        let runtime = runtime().map_err(MigrationError::ConnectionError)?;
+        let connection = runtime.block_on(AsyncPostgresConnection::new(config, table))?;
 
         Ok(Self {
-            connection: postgres_client,
+            runtime,
+            connection,
         })
     }
 }
 
 impl DatabaseConnection for PostgresConnection {
     fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
-        let sql = r#"
-        CREATE TABLE IF NOT EXISTS migration (
-            id SERIAL PRIMARY KEY NOT NULL,
-            name VARCHAR(255) UNIQUE NOT NULL,
-            time TIMESTAMP NOT NULL
-        );
-    "#;
+        self.runtime
+            .block_on(self.connection.create_migrations_table())
+    }
+
+    fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.upgrade_migrations_table())
+    }
+
+    fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
+        self.runtime
+            .block_on(self.connection.migration_table_exists())
+    }
+
+    fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
+        self.runtime.block_on(self.connection.get_migrations())
+    }
+
+    fn create_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.runtime
+            .block_on(self.connection.create_migration(name, time, checksum))
+    }
 
-        self.connection.execute(sql, &[])?;
+    fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        self.runtime.block_on(self.connection.execute_sql(sql))
+    }
+
+    fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.execute_sql_without_transaction(sql))
+    }
+
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        self.runtime.block_on(self.connection.query(sql))
+    }
+
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.runtime
+            .block_on(self.connection.apply_migration(name, time, checksum, sql))
+    }
+
+    fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.revert_migration(name, sql))
+    }
+
+    fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.remove_migration_by_id(id))
+    }
+
+    fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.remove_migration_by_name(name))
+    }
+
+    fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        self.runtime.block_on(self.connection.try_lock())
+    }
+
+    fn unlock(&mut self) -> Result<(), ConnectionError> {
+        self.runtime.block_on(self.connection.unlock())
+    }
+
+    fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        self.runtime.block_on(self.connection.force_unlock())
+    }
+}
+
+/// A Postgres database, for use from inside a tokio runtime.
+pub struct AsyncPostgresConnection {
+    connection: Client,
+    queries: Queries,
+    lock_key: i64,
+}
+
+impl AsyncPostgresConnection {
+    /// Connects to the database, and spawns the task that drives the connection onto the current
+    /// tokio runtime.
+    pub async fn new(config: &PostgresConfig, table: MigrationTable) -> Result<Self, DmtError> {
+        let (driver_config, mode) = driver_config(config)?;
+        let tls =
+            tls_connector(config.tls.as_ref(), mode).map_err(MigrationError::ConnectionError)?;
+
+        let (client, connection) = driver_config
+            .connect(tls)
+            .await
+            .map_err(|err| MigrationError::ConnectionError(err.into()))?;
+
+        // Errors of the connection surface through the client, as every request fails once the
+        // connection has closed.
+        tokio::spawn(connection);
+
+        let lock_key = if table.is_default() {
+            LOCK_KEY
+        } else {
+            client
+                .query_one(LOCK_KEY_SQL, &[&table.schema, &table.name])
+                .await
+                .map_err(|err| MigrationError::ConnectionError(err.into()))?
+                .get(0)
+        };
+
+        Ok(Self {
+            connection: client,
+            queries: Queries::new(table),
+            lock_key,
+        })
+    }
+}
+
+impl AsyncDatabaseConnection for AsyncPostgresConnection {
+    async fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        if let Some(create_schema) = &self.queries.create_schema {
+            self.connection.execute(create_schema, &[]).await?;
+        }
+        self.connection
+            .execute(&self.queries.create_table, &[])
+            .await?;
 
         Ok(())
     }
 
-    fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
-        let sql = r#"
-        SELECT 1 AS "exists" FROM information_schema."tables" WHERE "table_name" = 'migration'
-    "#;
+    async fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(&self.queries.upgrade_table, &[])
+            .await?;
 
-        let res = self.connection.query(sql, &[])?;
+        Ok(())
+    }
+
+    async fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
+        let table = &self.queries.table;
+        let res = self
+            .connection
+            .query(MIGRATION_TABLE_EXISTS_SQL, &[&table.schema, &table.name])
+            .await?;
 
         Ok(!res.is_empty())
     }
 
-    fn get_migrations(&mut self) -> Result<Vec<Migration>, ConnectionError> {
-        let sql = r#"
-        SELECT * FROM migration
-    "#;
+    async fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
+        // Tables created before checksums were recorded only get the column once they are
+        // upgraded.
+        let table = &self.queries.table;
+        let has_checksum = !self
+            .connection
+            .query(CHECKSUM_EXISTS_SQL, &[&table.schema, &table.name])
+            .await?
+            .is_empty();
+        let sql = if has_checksum {
+            &self.queries.get_migrations
+        } else {
+            &self.queries.get_legacy_migrations
+        };
 
-        let rows = self.connection.query(sql, &[])?;
+        let rows = self.connection.query(sql, &[]).await?;
 
-        let migrations: Vec<Migration> = rows.iter().map(|row| row.into()).collect();
+        let migrations: Vec<MigrationRecord> = rows.iter().map(|row| row.into()).collect();
 
         Ok(migrations)
     }
 
-    fn create_migration(
+    async fn create_migration(
         &mut self,
         name: &str,
         time: NaiveDateTime,
-    ) -> Result<Migration, ConnectionError> {
-        let sql = r#"
-        INSERT INTO migration (id, name, time) VALUES (DEFAULT, $1, $2)
-            RETURNING id, name, time;
-    "#;
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        let migration: MigrationRecord = self
+            .connection
+            .query_one(&self.queries.insert_migration, &[&name, &time, &checksum])
+            .await?
+            .into();
+
+        Ok(migration)
+    }
 
-        let migration: Migration = self.connection.query_one(sql, &[&name, &time])?.into();
+    async fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        let transaction = self.connection.transaction().await?;
+
+        transaction.batch_execute(sql).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        for statement in split_statements(sql) {
+            self.connection.batch_execute(statement).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        let messages = self.connection.simple_query(sql).await?;
+
+        Ok(simple_query_rows(messages))
+    }
+
+    async fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        let transaction = self.connection.transaction().await?;
+
+        transaction.batch_execute(sql).await?;
+
+        let migration: MigrationRecord = transaction
+            .query_one(&self.queries.insert_migration, &[&name, &time, &checksum])
+            .await?
+            .into();
+
+        transaction.commit().await?;
 
         Ok(migration)
     }
 
-    fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
-        let mut transaction = self.connection.transaction()?;
+    async fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        let transaction = self.connection.transaction().await?;
 
-        transaction.batch_execute(sql)?;
+        transaction.batch_execute(sql).await?;
 
-        transaction.commit()?;
+        transaction
+            .execute(&self.queries.delete_migration_by_name, &[&name])
+            .await?;
+
+        transaction.commit().await?;
 
         Ok(())
     }
 
-    fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
-        let sql = r#"
-       DELETE FROM migration WHERE id = $1
-    "#;
+    async fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(&self.queries.delete_migration_by_id, &[&id])
+            .await?;
 
-        self.connection.execute(sql, &[&id])?;
+        Ok(())
+    }
+
+    async fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(&self.queries.delete_migration_by_name, &[&name])
+            .await?;
 
         Ok(())
     }
 
-    fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
-        let sql = r#"
-       DELETE FROM migration WHERE name = $1
-    "#;
+    async fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        let locked: bool = self
+            .connection
+            .query_one(TRY_LOCK_SQL, &[&self.lock_key])
+            .await?
+            .get(0);
+
+        Ok(locked)
+    }
 
-        self.connection.execute(sql, &[&name])?;
+    async fn unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(UNLOCK_SQL, &[&self.lock_key])
+            .await?;
 
         Ok(())
     }
+
+    async fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(FORCE_UNLOCK_SQL, &[&self.lock_key])
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// The settings of the driver, made from the connection string with the separate keys of the
+/// config applied on top, and the TLS mode to connect with, which is the `mode` of the TLS
+/// settings or else the `sslmode` of the connection string.
+fn driver_config(
+    config: &PostgresConfig,
+) -> Result<(tokio_postgres::Config, TlsMode), ConfigError> {
+    let (connection_string, mode) = take_ssl_mode(&config.connection_string)?;
+
+    let mut driver_config: tokio_postgres::Config = connection_string.parse().map_err(|err| {
+        ConfigError::ParseError(format!("The postgres `connectionString` is invalid: {err}"))
+    })?;
+
+    // The driver adds to its list of hosts and ports rather than replacing it, so they cannot be
+    // given twice.
+    if let Some(host) = &config.host {
+        if !driver_config.get_hosts().is_empty() {
+            return Err(ConfigError::ParseError(
+                "The postgres `host` cannot be set when the connectionString names a host too"
+                    .to_string(),
+            ));
+        }
+        if host.is_empty() {
+            return Err(ConfigError::ParseError(
+                "The postgres `host` cannot be empty".to_string(),
+            ));
+        }
+
+        driver_config.host(host);
+    }
+    if let Some(port) = config.port {
+        if !driver_config.get_ports().is_empty() {
+            return Err(ConfigError::ParseError(
+                "The postgres `port` cannot be set when the connectionString names a port too"
+                    .to_string(),
+            ));
+        }
+        if port == 0 {
+            return Err(ConfigError::ParseError(
+                "The postgres `port` cannot be 0".to_string(),
+            ));
+        }
+
+        driver_config.port(port);
+    }
+    if driver_config.get_hosts().is_empty() {
+        return Err(ConfigError::ParseError(
+            "The postgres config needs a `host`, either as a key or in the connectionString"
+                .to_string(),
+        ));
+    }
+
+    if let Some(dbname) = &config.dbname {
+        driver_config.dbname(dbname);
+    }
+    if let Some(user) = &config.user {
+        driver_config.user(user);
+    }
+
+    match (&config.password, &config.password_file) {
+        (Some(password), None) => {
+            driver_config.password(password);
+        }
+        (None, Some(path)) => {
+            let password = fs::read_to_string(path).map_err(|err| {
+                ConfigError::ParseError(format!(
+                    "Could not read the postgres `passwordFile` {path}: {err}"
+                ))
+            })?;
+
+            driver_config.password(password.trim_end_matches(['\n', '\r']));
+        }
+        (Some(_), Some(_)) => {
+            return Err(ConfigError::ParseError(
+                "The postgres `password` and `passwordFile` cannot both be set".to_string(),
+            ))
+        }
+        (None, None) => {}
+    }
+
+    if let Some(application_name) = &config.application_name {
+        driver_config.application_name(application_name);
+    }
+    if let Some(seconds) = config.connect_timeout.filter(|seconds| *seconds > 0) {
+        driver_config.connect_timeout(Duration::from_secs(seconds));
+    }
+    if let Some(options) = &config.options {
+        driver_config.options(options);
+    }
+
+    let mode = config
+        .tls
+        .as_ref()
+        .and_then(|tls| tls.mode)
+        .or(mode)
+        .unwrap_or_default();
+    driver_config.ssl_mode(ssl_mode(mode));
+
+    Ok((driver_config, mode))
+}
+
+/// Reads the `sslmode` of a connection string in either the key/value or the URL form, and
+/// replaces it with one the driver accepts, as it does not know `verify-ca` and `verify-full`.
+/// The mode the driver is given is set after parsing anyway.
+fn take_ssl_mode(connection_string: &str) -> Result<(String, Option<TlsMode>), ConfigError> {
+    for (start, key) in connection_string.match_indices("sslmode") {
+        let at_key_start = connection_string[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || c == '?' || c == '&');
+
+        let Some(value) = connection_string[start + key.len()..]
+            .trim_start()
+            .strip_prefix('=')
+            .filter(|_| at_key_start)
+        else {
+            continue;
+        };
+
+        let value = value.trim_start();
+        let value_start = connection_string.len() - value.len();
+        let value_end = value_start
+            + value
+                .find(|c: char| c.is_whitespace() || c == '&')
+                .unwrap_or(value.len());
+
+        let mode = connection_string[value_start..value_end]
+            .trim_matches('\'')
+            .parse()?;
+
+        let connection_string = format!(
+            "{}prefer{}",
+            &connection_string[..value_start],
+            &connection_string[value_end..]
+        );
+
+        return Ok((connection_string, Some(mode)));
+    }
+
+    Ok((connection_string.to_string(), None))
+}
+
+/// The driver only knows whether to use TLS, as checking the certificate is up to the connector.
+fn ssl_mode(mode: TlsMode) -> SslMode {
+    match mode {
+        TlsMode::Disable => SslMode::Disable,
+        TlsMode::Prefer => SslMode::Prefer,
+        TlsMode::Require | TlsMode::VerifyCa | TlsMode::VerifyFull => SslMode::Require,
+    }
+}
+
+/// A connector that checks the certificate of the server as `mode` asks for. Like libpq, `require`
+/// only checks it if there is a CA file to check it against.
+#[cfg(feature = "native-tls")]
+fn tls_connector(tls: Option<&PostgresTlsConfig>, mode: TlsMode) -> Result<Tls, ConnectionError> {
+    let default = PostgresTlsConfig::default();
+    let tls = tls.unwrap_or(&default);
+    let mut builder = native_tls::TlsConnector::builder();
+
+    if let Some(path) = &tls.ca_file {
+        let certificates =
+            native_tls::Certificate::stack_from_pem(&read_pem(path)?).map_err(|err| {
+                ConnectionError::TlsError(format!(
+                    "Could not load the certificates in {path}: {err}"
+                ))
+            })?;
+
+        for certificate in certificates {
+            builder.add_root_certificate(certificate);
+        }
+    }
+
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let identity = native_tls::Identity::from_pkcs8(&read_pem(cert)?, &read_pem(key)?)
+                .map_err(|err| {
+                    ConnectionError::TlsError(format!(
+                        "Could not load the client certificate {cert} with the key {key}: {err}"
+                    ))
+                })?;
+
+            builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(ConnectionError::TlsError(
+                "A client certificate needs both a clientCert and a clientKey".to_string(),
+            ))
+        }
+    }
+
+    let (verify_certificate, verify_hostname) = verification(mode, tls.ca_file.is_some());
+
+    builder
+        .danger_accept_invalid_certs(!verify_certificate)
+        .danger_accept_invalid_hostnames(!verify_hostname);
+
+    let connector = builder
+        .build()
+        .map_err(|err| ConnectionError::TlsError(err.to_string()))?;
+
+    Ok(MakeTlsConnector::new(connector))
+}
+
+/// Whether `mode` checks the certificate of the server, and whether it checks that the certificate
+/// is for the host connected to.
+#[cfg(feature = "native-tls")]
+fn verification(mode: TlsMode, has_ca_file: bool) -> (bool, bool) {
+    match mode {
+        TlsMode::Disable | TlsMode::Prefer => (false, false),
+        TlsMode::Require => (has_ca_file, false),
+        TlsMode::VerifyCa => (true, false),
+        TlsMode::VerifyFull => (true, true),
+    }
+}
+
+/// Without the `native-tls` feature connections can only be made without TLS.
+#[cfg(not(feature = "native-tls"))]
+fn tls_connector(_tls: Option<&PostgresTlsConfig>, mode: TlsMode) -> Result<Tls, ConnectionError> {
+    match mode {
+        TlsMode::Disable | TlsMode::Prefer => Ok(NoTls),
+        _ => Err(ConnectionError::TlsError(format!(
+            "sslmode {mode} needs TLS, which dmt was built without. Build it with the `native-tls` \
+             feature to use TLS"
+        ))),
+    }
+}
+
+#[cfg(feature = "native-tls")]
+fn read_pem(path: &str) -> Result<Vec<u8>, ConnectionError> {
+    std::fs::read(path)
+        .map_err(|err| ConnectionError::TlsError(format!("Could not read {path}: {err}")))
+}
+
+/// The rows of a simple query, which returns every value as text, whatever its type.
+fn simple_query_rows(messages: Vec<SimpleQueryMessage>) -> Vec<Vec<Option<String>>> {
+    messages
+        .into_iter()
+        .filter_map(|message| match message {
+            SimpleQueryMessage::Row(row) => Some(
+                (0..row.len())
+                    .map(|i| row.get(i).map(str::to_string))
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Splits a script into its statements at the semicolons that are not inside a string, a quoted
+/// identifier, a dollar quoted body or a comment.
+fn split_statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"') => {
+                // A doubled quote inside a string is an escaped quote, which this skips over as
+                // the end of one string followed by the start of another. In an `E'...'` string
+                // a backslash escapes the character after it as well.
+                let escapes = quote == b'\''
+                    && i > 0
+                    && bytes[i - 1].eq_ignore_ascii_case(&b'e')
+                    && (i == 1 || !(bytes[i - 2].is_ascii_alphanumeric() || bytes[i - 2] == b'_'));
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if escapes && bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let mut depth = 0;
+                while i < bytes.len() {
+                    if bytes[i..].starts_with(b"/*") {
+                        depth += 1;
+                        i += 1;
+                    } else if bytes[i..].starts_with(b"*/") {
+                        depth -= 1;
+                        i += 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    i += 1;
+                }
+            }
+            b'$' => {
+                let tag_len = bytes[i + 1..]
+                    .iter()
+                    .position(|b| !(b.is_ascii_alphanumeric() || *b == b'_'));
+
+                if let Some(tag_len) = tag_len.filter(|len| bytes[i + 1 + len] == b'$') {
+                    let tag = &sql[i..i + tag_len + 2];
+                    i += tag.len();
+                    i = match sql[i..].find(tag) {
+                        Some(end) => i + end + tag.len() - 1,
+                        None => bytes.len(),
+                    };
+                }
+            }
+            b';' => {
+                statements.push(&sql[start..=i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    statements.push(&sql[start.min(sql.len())..]);
+    statements.retain(|statement| !statement.trim().is_empty());
+
+    statements
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::Duration;
+
+    use tokio_postgres::config::Host;
+
+    use super::{driver_config, split_statements, take_ssl_mode};
+    use crate::{ConfigError, PostgresConfig, PostgresTlsConfig, TlsMode};
+
+    #[test]
+    fn splits_statements_outside_quotes_and_comments() {
+        let sql = r#"
+CREATE INDEX CONCURRENTLY vehicle_name ON vehicle (name);
+-- a comment; with a semicolon
+INSERT INTO note VALUES ('it''s; here', "odd;name");
+INSERT INTO note VALUES (E'it\'s; here', 'C:\');
+/* block; /* nested; */ comment */
+CREATE FUNCTION f() RETURNS int AS $body$ SELECT 1; $body$ LANGUAGE sql;
+DO $$ BEGIN PERFORM 1; END $$;
+VACUUM"#;
+
+        let statements: Vec<&str> = split_statements(sql).iter().map(|s| s.trim()).collect();
+
+        assert_eq!(
+            statements,
+            [
+                "CREATE INDEX CONCURRENTLY vehicle_name ON vehicle (name);",
+                "-- a comment; with a semicolon\nINSERT INTO note VALUES ('it''s; here', \"odd;name\");",
+                r"INSERT INTO note VALUES (E'it\'s; here', 'C:\');",
+                "/* block; /* nested; */ comment */\nCREATE FUNCTION f() RETURNS int AS $body$ SELECT 1; $body$ LANGUAGE sql;",
+                "DO $$ BEGIN PERFORM 1; END $$;",
+                "VACUUM",
+            ]
+        );
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[test]
+    fn tls_modes_verify_like_libpq() {
+        use super::verification;
+
+        for (mode, has_ca_file, expected) in [
+            (TlsMode::Disable, true, (false, false)),
+            (TlsMode::Prefer, true, (false, false)),
+            (TlsMode::Require, false, (false, false)),
+            (TlsMode::Require, true, (true, false)),
+            (TlsMode::VerifyCa, false, (true, false)),
+            (TlsMode::VerifyCa, true, (true, false)),
+            (TlsMode::VerifyFull, false, (true, true)),
+            (TlsMode::VerifyFull, true, (true, true)),
+        ] {
+            assert_eq!(
+                verification(mode, has_ca_file),
+                expected,
+                "{mode} with a caFile: {has_ca_file}"
+            );
+        }
+    }
+
+    #[test]
+    fn takes_the_ssl_mode_of_either_connection_string_form() {
+        let (rest, mode) =
+            take_ssl_mode("host=db user=dmt sslmode = 'verify-full' dbname=app").unwrap();
+        assert_eq!(rest, "host=db user=dmt sslmode = prefer dbname=app");
+        assert_eq!(mode, Some(TlsMode::VerifyFull));
+
+        let (rest, mode) =
+            take_ssl_mode("postgres://dmt@db/app?sslmode=verify-ca&connect_timeout=5").unwrap();
+        assert_eq!(
+            rest,
+            "postgres://dmt@db/app?sslmode=prefer&connect_timeout=5"
+        );
+        assert_eq!(mode, Some(TlsMode::VerifyCa));
+
+        let (rest, mode) = take_ssl_mode("host=db password=nosslmode=disable").unwrap();
+        assert_eq!(rest, "host=db password=nosslmode=disable");
+        assert_eq!(mode, None);
+
+        assert!(take_ssl_mode("host=db sslmode=always").is_err());
+    }
+
+    #[test]
+    fn separate_keys_are_applied_to_the_connection_string() {
+        let dir = tempfile::tempdir().unwrap();
+        let password_file = dir.path().join("password");
+        fs::write(&password_file, "s3cret\n").unwrap();
+
+        let config = PostgresConfig {
+            connection_string: "host=db user=old application_name=app sslmode=disable".to_string(),
+            port: Some(5433),
+            user: Some("dmt".to_string()),
+            password_file: Some(password_file.display().to_string()),
+            connect_timeout: Some(5),
+            options: Some("-c search_path=app".to_string()),
+            tls: Some(PostgresTlsConfig {
+                mode: Some(TlsMode::Require),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let (driver_config, mode) = driver_config(&config).unwrap();
+
+        assert_eq!(driver_config.get_hosts(), [Host::Tcp("db".to_string())]);
+        assert_eq!(driver_config.get_ports(), [5433]);
+        assert_eq!(driver_config.get_user(), Some("dmt"));
+        assert_eq!(driver_config.get_password(), Some(&b"s3cret"[..]));
+        assert_eq!(driver_config.get_application_name(), Some("app"));
+        assert_eq!(
+            driver_config.get_connect_timeout(),
+            Some(&Duration::from_secs(5))
+        );
+        assert_eq!(driver_config.get_options(), Some("-c search_path=app"));
+        assert_eq!(mode, TlsMode::Require);
+    }
+
+    #[test]
+    fn invalid_keys_are_named_in_the_error() {
+        let error = |config: PostgresConfig| match driver_config(&config) {
+            Err(ConfigError::ParseError(msg)) => msg,
+            other => panic!("expected a parse error, got {other:?}"),
+        };
+        let with_host = |config: PostgresConfig| PostgresConfig {
+            host: Some("db".to_string()),
+            ..config
+        };
+
+        assert!(error(PostgresConfig {
+            connection_string: "host=other".to_string(),
+            ..with_host(PostgresConfig::default())
+        })
+        .contains("`host`"));
+        assert!(error(PostgresConfig::default()).contains("`host`"));
+        assert!(error(PostgresConfig {
+            port: Some(0),
+            ..with_host(PostgresConfig::default())
+        })
+        .contains("`port`"));
+        assert!(error(PostgresConfig {
+            password: Some("dev".to_string()),
+            password_file: Some("/run/secrets/password".to_string()),
+            ..with_host(PostgresConfig::default())
+        })
+        .contains("`passwordFile`"));
+        assert!(error(PostgresConfig {
+            password_file: Some("/nonexistent/password".to_string()),
+            ..with_host(PostgresConfig::default())
+        })
+        .contains("`passwordFile`"));
+        assert!(error(PostgresConfig {
+            connection_string: "port=abc".to_string(),
+            ..with_host(PostgresConfig::default())
+        })
+        .contains("`connectionString`"));
+        assert!(error(PostgresConfig {
+            connection_string: "sslmode=always".to_string(),
+            ..with_host(PostgresConfig::default())
+        })
+        .contains("sslmode"));
+    }
 }
@@ -0,0 +1,223 @@
+use std::error::Error;
+use std::io;
+use std::time::{Duration, Instant};
+
+use tokio_postgres::error::SqlState;
+
+use crate::{ConfigError, ConnectionError, RetryConfig};
+
+/// The delays between the attempts to connect to a database, following a [`RetryConfig`].
+pub(crate) struct Backoff {
+    attempts: u32,
+    max_attempts: u32,
+    delay: Duration,
+    factor: f64,
+    deadline: Option<Instant>,
+}
+
+impl Backoff {
+    /// Starts the delays of `config`, or allows a single attempt if there is none.
+    pub(crate) fn new(config: Option<&RetryConfig>) -> Result<Self, ConfigError> {
+        let Some(config) = config else {
+            return Ok(Self {
+                attempts: 0,
+                max_attempts: 1,
+                delay: Duration::ZERO,
+                factor: 1.0,
+                deadline: None,
+            });
+        };
+
+        if config.max_attempts == 0 {
+            return Err(ConfigError::ParseError(
+                "retry.maxAttempts must be at least 1".to_string(),
+            ));
+        }
+        if !(config.factor >= 1.0 && config.factor.is_finite()) {
+            return Err(ConfigError::ParseError(
+                "retry.factor must be a number of at least 1".to_string(),
+            ));
+        }
+        let delay = Duration::try_from_secs_f64(config.initial_delay).map_err(|_| {
+            ConfigError::ParseError(
+                "retry.initialDelay must be a number of seconds of at least 0".to_string(),
+            )
+        })?;
+
+        Ok(Self {
+            attempts: 0,
+            max_attempts: config.max_attempts,
+            delay,
+            factor: config.factor,
+            deadline: config
+                .deadline
+                .map(|deadline| Instant::now() + Duration::from_secs(deadline)),
+        })
+    }
+
+    /// The number of attempts that have failed so far.
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Records a failed attempt and returns how long to wait before the next one, or `None` if
+    /// there are no attempts or no time left.
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        self.attempts += 1;
+        if self.attempts >= self.max_attempts {
+            return None;
+        }
+
+        let mut delay = self.delay;
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return None;
+            }
+            delay = delay.min(left);
+        }
+
+        self.delay = Duration::try_from_secs_f64(self.delay.as_secs_f64() * self.factor)
+            .unwrap_or(Duration::MAX);
+
+        Some(delay)
+    }
+}
+
+/// Whether connecting again could succeed where `error` failed, which is only the case when the
+/// server could not be reached or is still starting up. Anything the server answered with, such
+/// as a wrong password or a database that does not exist, fails the same way every time.
+pub(crate) fn is_transient(error: &ConnectionError) -> bool {
+    match error {
+        ConnectionError::PostgresError(err) => match err.as_db_error() {
+            Some(db_error) => *db_error.code() == SqlState::CANNOT_CONNECT_NOW,
+            None => caused_by_unreachable(err),
+        },
+        ConnectionError::MysqlError(mysql::Error::IoError(err)) => is_unreachable(err),
+        ConnectionError::LibsqlError(libsql::Error::Hrana(err)) => {
+            caused_by_unreachable(err.as_ref())
+        }
+        _ => false,
+    }
+}
+
+/// Whether the first I/O error in the sources of `error`, if any, is [`is_unreachable`].
+fn caused_by_unreachable(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return is_unreachable(err);
+        }
+        source = err.source();
+    }
+
+    false
+}
+
+fn is_unreachable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::time::Duration;
+
+    use super::{is_transient, Backoff};
+    use crate::{ConnectionError, RetryConfig};
+
+    #[test]
+    fn delays_grow_until_attempts_run_out() {
+        let config = RetryConfig {
+            max_attempts: 4,
+            initial_delay: 0.5,
+            factor: 2.0,
+            deadline: None,
+        };
+        let mut backoff = Backoff::new(Some(&config)).unwrap();
+
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(500)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(1)));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_secs(2)));
+        assert_eq!(backoff.next_delay(), None);
+        assert_eq!(backoff.attempts(), 4);
+
+        assert_eq!(Backoff::new(None).unwrap().next_delay(), None);
+    }
+
+    #[test]
+    fn delays_stop_at_the_deadline() {
+        let config = RetryConfig {
+            max_attempts: u32::MAX,
+            initial_delay: 3600.0,
+            factor: 2.0,
+            deadline: Some(1),
+        };
+        let mut backoff = Backoff::new(Some(&config)).unwrap();
+
+        assert!(backoff.next_delay().unwrap() <= Duration::from_secs(1));
+
+        let config = RetryConfig {
+            deadline: Some(0),
+            ..config
+        };
+        assert_eq!(Backoff::new(Some(&config)).unwrap().next_delay(), None);
+    }
+
+    #[test]
+    fn only_unreachable_servers_are_retried() {
+        let io_error =
+            |kind: io::ErrorKind| ConnectionError::MysqlError(mysql::Error::IoError(kind.into()));
+
+        assert!(is_transient(&io_error(io::ErrorKind::ConnectionRefused)));
+        assert!(is_transient(&io_error(io::ErrorKind::TimedOut)));
+        assert!(!is_transient(&io_error(io::ErrorKind::PermissionDenied)));
+        assert!(!is_transient(&ConnectionError::LibsqlError(
+            libsql::Error::ConnectionFailed("unable to open database file".to_string())
+        )));
+        assert!(!is_transient(&ConnectionError::PostgresError(
+            "port=postgres"
+                .parse::<tokio_postgres::Config>()
+                .unwrap_err()
+        )));
+        assert!(!is_transient(&ConnectionError::TlsError(
+            "Could not read ca.crt".to_string()
+        )));
+    }
+
+    #[test]
+    fn invalid_settings_are_named() {
+        for (config, key) in [
+            (
+                RetryConfig {
+                    max_attempts: 0,
+                    ..RetryConfig::default()
+                },
+                "maxAttempts",
+            ),
+            (
+                RetryConfig {
+                    factor: 0.5,
+                    ..RetryConfig::default()
+                },
+                "factor",
+            ),
+            (
+                RetryConfig {
+                    initial_delay: -1.0,
+                    ..RetryConfig::default()
+                },
+                "initialDelay",
+            ),
+        ] {
+            let err = Backoff::new(Some(&config)).err().unwrap();
+            assert!(err.to_string().contains(key), "{err}");
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use chrono::NaiveDateTime;
+
+use crate::commands::MigrationRecord;
+use crate::config::SqliteConfig;
+use crate::ConnectionError;
+
+use super::turso::TursoConnection;
+use super::{DatabaseConnection, MigrationTable};
+
+/// A plain on-disk SQLite database.
+///
+/// SQLite speaks the same dialect as libSQL, so this reuses the local mode of the Turso backend
+/// rather than linking a second copy of SQLite.
+pub struct SqliteConnection {
+    connection: TursoConnection,
+}
+
+impl SqliteConnection {
+    pub fn new(config: &SqliteConfig, table: MigrationTable) -> Result<Self, ConnectionError> {
+        Ok(Self {
+            connection: TursoConnection::local(&config.path, table)?,
+        })
+    }
+}
+
+impl DatabaseConnection for SqliteConnection {
+    fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        self.connection.create_migrations_table()
+    }
+
+    fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        self.connection.upgrade_migrations_table()
+    }
+
+    fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
+        self.connection.migration_table_exists()
+    }
+
+    fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
+        self.connection.get_migrations()
+    }
+
+    fn create_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.connection.create_migration(name, time, checksum)
+    }
+
+    fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        self.connection.execute_sql(sql)
+    }
+
+    fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        self.connection.execute_sql_without_transaction(sql)
+    }
+
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        self.connection.query(sql)
+    }
+
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.connection.apply_migration(name, time, checksum, sql)
+    }
+
+    fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        self.connection.revert_migration(name, sql)
+    }
+
+    fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
+        self.connection.remove_migration_by_id(id)
+    }
+
+    fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
+        self.connection.remove_migration_by_name(name)
+    }
+
+    fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        self.connection.try_lock()
+    }
+
+    fn unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection.unlock()
+    }
+
+    fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection.force_unlock()
+    }
+}
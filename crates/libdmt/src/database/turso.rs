@@ -1,51 +1,538 @@
-use libsql::Connection;
+use std::future::Future;
+use std::path::Path;
 
-use crate::commands::Migration;
+use chrono::NaiveDateTime;
+use libsql::{Builder, Connection, Database, Row, Transaction};
+use tokio::runtime::Runtime;
+
+use crate::commands::MigrationRecord;
 use crate::config::TursoConfig;
 use crate::ConnectionError;
 
-use super::DatabaseConnection;
+use super::{
+    quote_identifier, runtime, AsyncDatabaseConnection, DatabaseConnection, MigrationTable,
+};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// The statements that use the migration table and its lock table, whose names come from the
+/// config.
+struct Queries {
+    table: MigrationTable,
+    create_table: String,
+    add_checksum: String,
+    table_exists: String,
+    get_migrations: String,
+    get_legacy_migrations: String,
+    insert_migration: String,
+    delete_migration_by_name: String,
+    delete_migration_by_id: String,
+    create_lock_table: String,
+    lock: String,
+    unlock: String,
+    drop_lock_table: String,
+}
+
+impl Queries {
+    fn new(table: MigrationTable) -> Self {
+        let name = table.qualified('"');
+        let lock_name = table
+            .sibling(&format!("{}_lock", table.name))
+            .qualified('"');
+        let master = match &table.schema {
+            Some(schema) => format!("{}.sqlite_master", quote_identifier(schema, '"')),
+            None => "sqlite_master".to_string(),
+        };
+
+        Self {
+            create_table: format!(
+                r#"
+        CREATE TABLE IF NOT EXISTS {name} (
+            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            time TIMESTAMP NOT NULL,
+            checksum VARCHAR(64)
+        );
+    "#
+            ),
+            add_checksum: format!("ALTER TABLE {name} ADD COLUMN checksum VARCHAR(64);"),
+            table_exists: format!(
+                r#"
+        SELECT 1 AS "exists" FROM {master} WHERE type = 'table' AND name = ?1
+    "#
+            ),
+            get_migrations: format!(
+                r#"
+        SELECT id, name, time, checksum FROM {name}
+    "#
+            ),
+            get_legacy_migrations: format!(
+                r#"
+        SELECT id, name, time, NULL AS checksum FROM {name}
+    "#
+            ),
+            insert_migration: format!(
+                r#"
+        INSERT INTO {name} (name, time, checksum) VALUES (?1, ?2, ?3)
+            RETURNING id, name, time, checksum;
+    "#
+            ),
+            delete_migration_by_name: format!(
+                r#"
+        DELETE FROM {name} WHERE name = ?1
+    "#
+            ),
+            delete_migration_by_id: format!(
+                r#"
+        DELETE FROM {name} WHERE id = ?1
+    "#
+            ),
+            create_lock_table: format!(
+                r#"
+        CREATE TABLE IF NOT EXISTS {lock_name} (
+            id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+            time TIMESTAMP NOT NULL
+        );
+    "#
+            ),
+            lock: format!(
+                r#"
+        INSERT OR IGNORE INTO {lock_name} (id, time) VALUES (1, ?1)
+    "#
+            ),
+            unlock: format!(
+                r#"
+        DELETE FROM {lock_name}
+    "#
+            ),
+            drop_lock_table: format!(
+                r#"
+        DROP TABLE IF EXISTS {lock_name}
+    "#
+            ),
+            table,
+        }
+    }
+}
+
+impl TryFrom<Row> for MigrationRecord {
+    type Error = libsql::Error;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let time: String = row.get(2)?;
+
+        Ok(MigrationRecord {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            time: NaiveDateTime::parse_from_str(&time, TIME_FORMAT)
+                .map_err(|_| libsql::Error::InvalidColumnType)?,
+            checksum: row.get(3)?,
+        })
+    }
+}
 
+/// A Turso or local libSQL database, driven by a current thread runtime of its own.
 pub struct TursoConnection {
-    connection: Connection,
+    runtime: Runtime,
+    connection: AsyncTursoConnection,
 }
 
 impl TursoConnection {
-    pub fn new(config: &TursoConfig) -> Result<Self, ConnectionError> {
-        todo!()
+    pub fn new(config: &TursoConfig, table: MigrationTable) -> Result<Self, ConnectionError> {
+        let runtime = runtime()?;
+        let connection = runtime.block_on(AsyncTursoConnection::new(config, table))?;
+
+        Ok(Self {
+            runtime,
+            connection,
+        })
+    }
+
+    /// Opens a local database file, creating it if it does not exist yet.
+    pub fn local(path: impl AsRef<Path>, table: MigrationTable) -> Result<Self, ConnectionError> {
+        let runtime = runtime()?;
+        let connection = runtime.block_on(AsyncTursoConnection::local(path, table))?;
+
+        Ok(Self {
+            runtime,
+            connection,
+        })
     }
 }
 
 impl DatabaseConnection for TursoConnection {
     fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
-        todo!()
+        self.runtime
+            .block_on(self.connection.create_migrations_table())
+    }
+
+    fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.upgrade_migrations_table())
     }
 
     fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
-        todo!()
+        self.runtime
+            .block_on(self.connection.migration_table_exists())
     }
 
-    fn get_migrations(&mut self) -> Result<Vec<Migration>, ConnectionError> {
-        todo!()
+    fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
+        self.runtime.block_on(self.connection.get_migrations())
     }
 
     fn create_migration(
         &mut self,
         name: &str,
-        time: chrono::NaiveDateTime,
-    ) -> Result<Migration, ConnectionError> {
-        todo!()
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.runtime
+            .block_on(self.connection.create_migration(name, time, checksum))
     }
 
     fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
-        todo!()
+        self.runtime.block_on(self.connection.execute_sql(sql))
+    }
+
+    fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.execute_sql_without_transaction(sql))
+    }
+
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        self.runtime.block_on(self.connection.query(sql))
+    }
+
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        self.runtime
+            .block_on(self.connection.apply_migration(name, time, checksum, sql))
+    }
+
+    fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        self.runtime
+            .block_on(self.connection.revert_migration(name, sql))
     }
 
     fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
-        todo!()
+        self.runtime
+            .block_on(self.connection.remove_migration_by_id(id))
     }
 
     fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
-        todo!()
+        self.runtime
+            .block_on(self.connection.remove_migration_by_name(name))
+    }
+
+    fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        self.runtime.block_on(self.connection.try_lock())
+    }
+
+    fn unlock(&mut self) -> Result<(), ConnectionError> {
+        self.runtime.block_on(self.connection.unlock())
+    }
+
+    fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        self.runtime.block_on(self.connection.force_unlock())
+    }
+}
+
+/// A Turso or local libSQL database, for use from inside a tokio runtime.
+pub struct AsyncTursoConnection {
+    // Kept alive for as long as the connection, as local databases are closed when dropped.
+    _database: Database,
+    connection: Connection,
+    queries: Queries,
+}
+
+impl AsyncTursoConnection {
+    pub async fn new(config: &TursoConfig, table: MigrationTable) -> Result<Self, ConnectionError> {
+        if config.url == ":memory:" {
+            Self::local(":memory:", table).await
+        } else if let Some(path) = config.url.strip_prefix("file:") {
+            Self::local(path, table).await
+        } else {
+            let database = Builder::new_remote(config.url.clone(), config.token.clone()).build();
+
+            Self::open(database, table).await
+        }
+    }
+
+    /// Opens a local database file, creating it if it does not exist yet.
+    pub async fn local(
+        path: impl AsRef<Path>,
+        table: MigrationTable,
+    ) -> Result<Self, ConnectionError> {
+        Self::open(Builder::new_local(path).build(), table).await
+    }
+
+    async fn open(
+        database: impl Future<Output = Result<Database, libsql::Error>>,
+        table: MigrationTable,
+    ) -> Result<Self, ConnectionError> {
+        let database = database.await?;
+        let connection = database.connect()?;
+
+        Ok(Self {
+            _database: database,
+            connection,
+            queries: Queries::new(table),
+        })
+    }
+
+    /// Whether the migration table has a `checksum` column, which tables created before checksums
+    /// were recorded only get once they are upgraded.
+    async fn has_checksum(&self) -> Result<bool, ConnectionError> {
+        let sql = r#"
+        SELECT 1 FROM pragma_table_info(?1, ?2) WHERE name = 'checksum'
+    "#;
+
+        let table = &self.queries.table;
+        let mut rows = self
+            .connection
+            .query(sql, (table.name.as_str(), table.schema.as_deref()))
+            .await?;
+
+        Ok(rows.next().await?.is_some())
+    }
+}
+
+impl AsyncDatabaseConnection for AsyncTursoConnection {
+    async fn create_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(&self.queries.create_table, ())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn upgrade_migrations_table(&mut self) -> Result<(), ConnectionError> {
+        // SQLite has no `ADD COLUMN IF NOT EXISTS`, so the columns are looked up first.
+        if !self.has_checksum().await? {
+            self.connection
+                .execute(&self.queries.add_checksum, ())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn migration_table_exists(&mut self) -> Result<bool, ConnectionError> {
+        let mut rows = self
+            .connection
+            .query(
+                &self.queries.table_exists,
+                [self.queries.table.name.as_str()],
+            )
+            .await?;
+
+        Ok(rows.next().await?.is_some())
+    }
+
+    async fn get_migrations(&mut self) -> Result<Vec<MigrationRecord>, ConnectionError> {
+        let sql = if self.has_checksum().await? {
+            &self.queries.get_migrations
+        } else {
+            &self.queries.get_legacy_migrations
+        };
+        let mut rows = self.connection.query(sql, ()).await?;
+
+        let mut migrations = Vec::new();
+        while let Some(row) = rows.next().await? {
+            migrations.push(MigrationRecord::try_from(row)?);
+        }
+
+        Ok(migrations)
+    }
+
+    async fn create_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        let migration = insert_migration(
+            &self.connection,
+            &self.queries.insert_migration,
+            name,
+            time,
+            checksum,
+        )
+        .await?;
+
+        Ok(migration)
+    }
+
+    async fn execute_sql(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        let transaction = self.connection.transaction().await?;
+
+        let result = transaction.execute_batch(sql).await.map(|_| ());
+
+        finish_transaction(transaction, result).await?;
+
+        Ok(())
+    }
+
+    async fn execute_sql_without_transaction(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        // Outside a transaction, libSQL commits each statement of a batch as it runs.
+        self.connection.execute_batch(sql).await?;
+
+        Ok(())
+    }
+
+    async fn query(&mut self, sql: &str) -> Result<Vec<Vec<Option<String>>>, ConnectionError> {
+        let mut rows = self.connection.query(sql, ()).await?;
+        let mut values = Vec::new();
+
+        while let Some(row) = rows.next().await? {
+            let row = (0..row.column_count())
+                .map(|i| {
+                    Ok(match row.get_value(i)? {
+                        libsql::Value::Null => None,
+                        libsql::Value::Integer(value) => Some(value.to_string()),
+                        libsql::Value::Real(value) => Some(value.to_string()),
+                        libsql::Value::Text(value) => Some(value),
+                        libsql::Value::Blob(value) => {
+                            Some(String::from_utf8_lossy(&value).into_owned())
+                        }
+                    })
+                })
+                .collect::<Result<Vec<_>, libsql::Error>>()?;
+
+            values.push(row);
+        }
+
+        Ok(values)
+    }
+
+    async fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> Result<MigrationRecord, ConnectionError> {
+        let transaction = self.connection.transaction().await?;
+
+        let result = async {
+            transaction.execute_batch(sql).await?;
+
+            insert_migration(
+                &transaction,
+                &self.queries.insert_migration,
+                name,
+                time,
+                Some(checksum),
+            )
+            .await
+        }
+        .await;
+
+        Ok(finish_transaction(transaction, result).await?)
+    }
+
+    async fn revert_migration(&mut self, name: &str, sql: &str) -> Result<(), ConnectionError> {
+        let transaction = self.connection.transaction().await?;
+
+        let result = async {
+            transaction.execute_batch(sql).await?;
+            transaction
+                .execute(&self.queries.delete_migration_by_name, [name])
+                .await?;
+
+            Ok(())
+        }
+        .await;
+
+        finish_transaction(transaction, result).await?;
+
+        Ok(())
+    }
+
+    async fn remove_migration_by_id(&mut self, id: i32) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(&self.queries.delete_migration_by_id, [id])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_migration_by_name(&mut self, name: &str) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(&self.queries.delete_migration_by_name, [name])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn try_lock(&mut self) -> Result<bool, ConnectionError> {
+        // SQLite has no advisory locks, so the lock is a single row in a table of its own, named
+        // after the migration table.
+        let time = chrono::Utc::now()
+            .naive_utc()
+            .format(TIME_FORMAT)
+            .to_string();
+
+        self.connection
+            .execute(&self.queries.create_lock_table, ())
+            .await?;
+        let inserted = self.connection.execute(&self.queries.lock, [time]).await?;
+
+        Ok(inserted == 1)
+    }
+
+    async fn unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection.execute(&self.queries.unlock, ()).await?;
+
+        Ok(())
+    }
+
+    async fn force_unlock(&mut self) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(&self.queries.drop_lock_table, ())
+            .await?;
+
+        Ok(())
+    }
+}
+
+async fn insert_migration(
+    connection: &Connection,
+    sql: &str,
+    name: &str,
+    time: NaiveDateTime,
+    checksum: Option<&str>,
+) -> Result<MigrationRecord, libsql::Error> {
+    let time = time.format(TIME_FORMAT).to_string();
+
+    let mut rows = connection.query(sql, (name, time, checksum)).await?;
+
+    let row = rows
+        .next()
+        .await?
+        .ok_or(libsql::Error::QueryReturnedNoRows)?;
+
+    MigrationRecord::try_from(row)
+}
+
+/// Commits the transaction if `result` is `Ok`, and rolls it back otherwise.
+async fn finish_transaction<T>(
+    transaction: Transaction,
+    result: Result<T, libsql::Error>,
+) -> Result<T, libsql::Error> {
+    match result {
+        Ok(value) => {
+            transaction.commit().await?;
+            Ok(value)
+        }
+        Err(err) => {
+            transaction.rollback().await?;
+            Err(err)
+        }
     }
 }
@@ -3,8 +3,57 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::MigrationError;
+use sha2::{Digest, Sha256};
 
+use crate::{MigrationError, MigrationId};
+
+/// The line that starts the `up` section of a single file migration.
+pub(crate) const UP_MARKER: &str = "-- dmt:up";
+/// The line that starts the `down` section of a single file migration.
+pub(crate) const DOWN_MARKER: &str = "-- dmt:down";
+/// A line in the leading comments of a migration's SQL that makes it run outside a transaction.
+pub(crate) const NO_TRANSACTION_DIRECTIVE: &str = "-- dmt:no-transaction";
+
+/// The SQL of a migration.
+pub struct MigrationSql {
+    pub up: String,
+    pub down: String,
+    /// Whether the migration runs inside a transaction, which is the case unless its `up` or
+    /// `down` SQL starts with a `-- dmt:no-transaction` line.
+    pub transactional: bool,
+}
+
+impl MigrationSql {
+    /// The SQL of a migration directory, from its `up.sql` and `down.sql`.
+    fn from_dir(up: String, down: String) -> Self {
+        let transactional =
+            !has_no_transaction_directive(&up) && !has_no_transaction_directive(&down);
+
+        Self {
+            up,
+            down,
+            transactional,
+        }
+    }
+
+    /// Returns a hex encoded SHA-256 hash of the `up` and `down` SQL. Each is hashed after its
+    /// length, so that moving text from the end of one to the start of the other changes it too.
+    pub fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        for sql in [&self.up, &self.down] {
+            hasher.update((sql.len() as u64).to_le_bytes());
+            hasher.update(sql);
+        }
+
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// A directory of migrations.
+///
+/// A migration is either a `YYYYMMDDHHMMSS_name` directory holding an `up.sql` and a `down.sql`,
+/// or a single `YYYYMMDDHHMMSS_name.sql` file whose `up` and `down` sections start with a
+/// `-- dmt:up` and a `-- dmt:down` line. Both kinds can be mixed in the same directory.
 pub struct MigrationDir {
     path: PathBuf,
 }
@@ -19,25 +68,72 @@ impl MigrationDir {
         }
     }
 
-    pub fn get_migration_dir_names(&self) -> Result<Vec<String>, MigrationError> {
+    /// Returns the ids of all migrations, in the order they should be run.
+    pub fn get_migration_ids(&self) -> Result<Vec<MigrationId>, MigrationError> {
+        let mut ids = self
+            .get_migration_names()?
+            .iter()
+            .map(|name| name.parse())
+            .collect::<Result<Vec<MigrationId>, _>>()?;
+
+        ids.sort();
+
+        if let Some(duplicate) = ids.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MigrationError::DuplicateMigration(duplicate[0].to_string()));
+        }
+
+        Ok(ids)
+    }
+
+    /// Reads every migration, in the order they should be run.
+    pub fn read_migrations(&self) -> Result<Vec<(MigrationId, MigrationSql)>, MigrationError> {
+        self.get_migration_ids()?
+            .into_iter()
+            .map(|id| {
+                let sql = self.read_migration(&id.to_string())?;
+                Ok((id, sql))
+            })
+            .collect()
+    }
+
+    /// The names of the migration directories and single file migrations, without the `.sql`
+    /// extension.
+    fn get_migration_names(&self) -> Result<Vec<String>, MigrationError> {
         let dir = self.dir_entries()?;
 
         Ok(dir
-            .filter(|entry| match entry {
-                Ok(entry) => {
-                    if let Ok(filetype) = entry.file_type() {
-                        filetype.is_dir()
-                    } else {
-                        false
-                    }
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let filetype = entry.file_type().ok()?;
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                if filetype.is_dir() {
+                    Some(name)
+                } else if filetype.is_file() {
+                    name.strip_suffix(".sql").map(str::to_string)
+                } else {
+                    None
                 }
-                Err(_) => false,
             })
-            .map(|dir| dir.unwrap().file_name().to_string_lossy().to_string())
             .collect())
     }
 
-    pub fn get_file_contents(&self, path: &str) -> Result<String, MigrationError> {
+    /// Reads the `up` and `down` SQL of a migration, in whichever format it is stored.
+    pub fn read_migration(&self, migration: &str) -> Result<MigrationSql, MigrationError> {
+        let single_file = format!("{}.sql", migration);
+
+        if self.path.join(&single_file).is_file() {
+            let contents = self.get_file_contents(&single_file)?;
+            return parse_single_file(migration, &contents);
+        }
+
+        let up = self.get_file_contents(&format!("{}/up.sql", migration))?;
+        let down = self.get_file_contents(&format!("{}/down.sql", migration))?;
+
+        Ok(MigrationSql::from_dir(up, down))
+    }
+
+    fn get_file_contents(&self, path: &str) -> Result<String, MigrationError> {
         let mut file_path = self.path.clone();
         file_path.push(path);
 
@@ -48,3 +144,175 @@ impl MigrationDir {
         Ok(fs::read_dir(&self.path)?)
     }
 }
+
+/// A migration embedded in the binary by [`embed_migrations!`](crate::embed_migrations).
+#[derive(Debug, Clone, Copy)]
+pub enum EmbeddedMigration {
+    /// A migration directory, with the contents of its `up.sql` and `down.sql`.
+    Dir {
+        name: &'static str,
+        up: &'static str,
+        down: &'static str,
+    },
+    /// A single file migration, with the contents of its `.sql` file.
+    SingleFile {
+        name: &'static str,
+        contents: &'static str,
+    },
+}
+
+/// The migrations of a directory, read at compile time by
+/// [`embed_migrations!`](crate::embed_migrations) so that the directory does not have to be
+/// shipped with the binary.
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedMigrations {
+    migrations: &'static [EmbeddedMigration],
+}
+
+impl EmbeddedMigrations {
+    pub const fn new(migrations: &'static [EmbeddedMigration]) -> Self {
+        Self { migrations }
+    }
+
+    /// Parses every migration, in the order they should be run.
+    pub(crate) fn read_migrations(
+        &self,
+    ) -> Result<Vec<(MigrationId, MigrationSql)>, MigrationError> {
+        let mut migrations = self
+            .migrations
+            .iter()
+            .map(|migration| match *migration {
+                EmbeddedMigration::Dir { name, up, down } => Ok((
+                    name.parse()?,
+                    MigrationSql::from_dir(up.to_string(), down.to_string()),
+                )),
+                EmbeddedMigration::SingleFile { name, contents } => {
+                    Ok((name.parse()?, parse_single_file(name, contents)?))
+                }
+            })
+            .collect::<Result<Vec<(MigrationId, MigrationSql)>, MigrationError>>()?;
+
+        migrations.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(migrations)
+    }
+}
+
+/// Where a [`Migrator`](crate::Migrator) reads its migrations from.
+///
+/// A path converts into a [`MigrationSource::Dir`], and a reference to a static
+/// [`EmbeddedMigrations`] into a [`MigrationSource::Embedded`].
+#[derive(Debug, Clone)]
+pub enum MigrationSource {
+    /// A directory of migrations, read whenever the migrations are run.
+    Dir(PathBuf),
+    /// Migrations embedded in the binary at compile time.
+    Embedded(&'static EmbeddedMigrations),
+}
+
+impl MigrationSource {
+    /// Reads every migration, in the order they should be run.
+    pub(crate) fn read_migrations(
+        &self,
+    ) -> Result<Vec<(MigrationId, MigrationSql)>, MigrationError> {
+        match self {
+            Self::Dir(path) => MigrationDir::new(path).read_migrations(),
+            Self::Embedded(migrations) => migrations.read_migrations(),
+        }
+    }
+}
+
+impl From<PathBuf> for MigrationSource {
+    fn from(path: PathBuf) -> Self {
+        Self::Dir(path)
+    }
+}
+
+impl<P> From<&P> for MigrationSource
+where
+    P: AsRef<Path> + ?Sized,
+{
+    fn from(path: &P) -> Self {
+        Self::Dir(path.as_ref().into())
+    }
+}
+
+impl From<&'static EmbeddedMigrations> for MigrationSource {
+    fn from(migrations: &'static EmbeddedMigrations) -> Self {
+        Self::Embedded(migrations)
+    }
+}
+
+/// Splits a single file migration into its `up` and `down` sections. Anything before the
+/// `-- dmt:up` line is ignored, and a missing `-- dmt:down` section is an empty one.
+///
+/// A `-- dmt:no-transaction` line applies to the whole migration, whether it is in the comments
+/// before the `-- dmt:up` line or at the start of either section.
+fn parse_single_file(migration: &str, contents: &str) -> Result<MigrationSql, MigrationError> {
+    let malformed = |reason: &str| MigrationError::MalformedMigration {
+        name: migration.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let mut up: Option<String> = None;
+    let mut down: Option<String> = None;
+
+    for line in contents.lines() {
+        match line.trim() {
+            UP_MARKER if up.is_some() => return Err(malformed("more than one `-- dmt:up` line")),
+            UP_MARKER if down.is_some() => {
+                return Err(malformed("`-- dmt:up` comes after `-- dmt:down`"))
+            }
+            UP_MARKER => up = Some(String::new()),
+            DOWN_MARKER if down.is_some() => {
+                return Err(malformed("more than one `-- dmt:down` line"))
+            }
+            DOWN_MARKER => down = Some(String::new()),
+            _ => {
+                if let Some(section) = down.as_mut().or(up.as_mut()) {
+                    section.push_str(line);
+                    section.push('\n');
+                }
+            }
+        }
+    }
+
+    let up = up.ok_or_else(|| malformed("no `-- dmt:up` line"))?;
+    let down = down.unwrap_or_default();
+    let transactional =
+        !has_no_transaction_directive(contents) && !has_no_transaction_directive(&down);
+
+    Ok(MigrationSql {
+        up,
+        down,
+        transactional,
+    })
+}
+
+/// Whether the comments `sql` starts with include a `-- dmt:no-transaction` line.
+fn has_no_transaction_directive(sql: &str) -> bool {
+    sql.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .take_while(|line| line.starts_with("--"))
+        .any(|line| line == NO_TRANSACTION_DIRECTIVE)
+}
+
+#[cfg(test)]
+mod test {
+    use super::MigrationSql;
+
+    #[test]
+    fn checksums_tell_up_from_down() {
+        let sql = |up: &str, down: &str| MigrationSql::from_dir(up.to_string(), down.to_string());
+
+        assert_eq!(
+            sql("CREATE TABLE a ();", "DROP TABLE a;").checksum(),
+            sql("CREATE TABLE a ();", "DROP TABLE a;").checksum()
+        );
+        assert_ne!(
+            sql("CREATE TABLE a ();", "DROP TABLE a;").checksum(),
+            sql("CREATE TABLE a ();DROP", " TABLE a;").checksum()
+        );
+    }
+}
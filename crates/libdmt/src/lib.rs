@@ -1,17 +1,43 @@
 mod commands;
-mod config;
 mod database;
 mod io;
+mod migration;
+mod migration_id;
+mod migrator;
+mod report;
+mod runner;
 
 use std::error::Error;
 use std::fmt::Display;
 use std::io as stdio;
 use std::process::{ExitCode, Termination};
-
-pub use commands::{new_migration, rollback_migrations, run_migrations};
-pub use config::{Database, DmtConfig, MigrationConfig};
+use std::time::Duration;
+
+use libdmt_config as config;
+
+pub use commands::{
+    migration_status, new_migration, new_single_file_migration, rollback_migrations,
+    run_migrations, run_migrations_to, verify_migrations, MigrationRecord, MigrationState,
+    MigrationStatus, PlannedMigration, RollbackTarget,
+};
+#[cfg(feature = "async")]
+pub use commands::{rollback_migrations_async, run_migrations_async, run_migrations_to_async};
+#[cfg(feature = "async")]
+pub use database::{AsyncDatabaseConnection, AsyncMigrationDatabase};
 pub use database::{DatabaseConnection, MigrationDatabase};
-pub use libdmt_macros::migrate;
+pub use io::{EmbeddedMigration, EmbeddedMigrations, MigrationSource};
+pub use libdmt_config::{
+    ConfigError, ConfigFormat, Database, DmtConfig, MigrationConfig, PostgresConfig,
+    PostgresTlsConfig, RetryConfig, TlsMode, CONFIG_FILE_NAMES, PROFILE_ENV_VAR,
+};
+pub use libdmt_macros::{embed_migrations, migrate, migrate_or_panic};
+pub use migration::Migration;
+pub use migration_id::MigrationId;
+pub use migrator::Migrator;
+pub use report::{
+    Direction, HumanReporter, JsonReporter, MigrationEvent, MigrationReport, QuietReporter,
+    Reporter,
+};
 
 #[derive(Debug)]
 pub enum DmtError {
@@ -61,43 +87,24 @@ impl From<MigrationError> for DmtError {
     }
 }
 
-#[derive(Debug)]
-pub enum ConfigError {
-    FileError(stdio::Error),
-    UnrecognizedConfigFormat(String),
-    ParseError(String),
-}
-
-impl From<stdio::Error> for ConfigError {
-    fn from(err: stdio::Error) -> Self {
-        Self::FileError(err)
-    }
-}
-
-impl Display for ConfigError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ConfigError::FileError(error) => f.write_str(error.to_string().as_str()),
-            ConfigError::UnrecognizedConfigFormat(msg) => f.write_str(msg),
-            ConfigError::ParseError(msg) => f.write_str(msg),
-        }
-    }
-}
-
-impl Error for ConfigError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        match self {
-            ConfigError::FileError(error) => error.source(),
-            ConfigError::UnrecognizedConfigFormat(_) => None,
-            ConfigError::ParseError(_) => None,
-        }
-    }
-}
-
 #[derive(Debug)]
 pub enum MigrationError {
     FileError(stdio::Error),
     ConnectionError(ConnectionError),
+    InvalidMigrationName(String),
+    DuplicateMigration(String),
+    MissingMigration(String),
+    MigrationFailed {
+        name: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    MalformedMigration {
+        name: String,
+        reason: String,
+    },
+    UnknownTarget(String),
+    ChecksumMismatch(Vec<String>),
+    LockTimeout(Duration),
 }
 
 impl From<stdio::Error> for MigrationError {
@@ -117,6 +124,33 @@ impl Display for MigrationError {
         let msg = match self {
             Self::ConnectionError(err) => err.to_string(),
             Self::FileError(error) => error.to_string(),
+            Self::InvalidMigrationName(name) => {
+                format!("Invalid migration name `{name}`. Expected the format YYYYMMDDHHMMSS_name")
+            }
+            Self::DuplicateMigration(name) => {
+                format!("Migration `{name}` is defined more than once")
+            }
+            Self::MissingMigration(name) => format!(
+                "Migration `{name}` has been run but no longer exists, so it cannot be rolled back"
+            ),
+            Self::MigrationFailed { name, source } => {
+                format!("Migration `{name}` failed: {source}")
+            }
+            Self::MalformedMigration { name, reason } => {
+                format!("Migration `{name}` is malformed: {reason}")
+            }
+            Self::UnknownTarget(version) => {
+                format!("No migration matching `{version}` exists in the migrations directory")
+            }
+            Self::ChecksumMismatch(names) => format!(
+                "The following applied migrations have changed on disk since they were run: {}",
+                names.join(", ")
+            ),
+            Self::LockTimeout(timeout) => format!(
+                "Timed out after {}s waiting for another migration to release the migration lock. \
+                 If no migration is running, release it with `dmt unlock`",
+                timeout.as_secs()
+            ),
         };
 
         f.write_str(&msg)
@@ -128,18 +162,48 @@ impl Error for MigrationError {
         match self {
             Self::ConnectionError(error) => error.source(),
             Self::FileError(error) => Some(error),
+            Self::InvalidMigrationName(_) => None,
+            Self::DuplicateMigration(_) => None,
+            Self::MissingMigration(_) => None,
+            Self::MigrationFailed { source, .. } => Some(source.as_ref()),
+            Self::MalformedMigration { .. } => None,
+            Self::UnknownTarget(_) => None,
+            Self::ChecksumMismatch(_) => None,
+            Self::LockTimeout(_) => None,
         }
     }
 }
 
 #[derive(Debug)]
 pub enum ConnectionError {
-    PostgresError(postgres::Error),
+    PostgresError(tokio_postgres::Error),
+    LibsqlError(libsql::Error),
+    MysqlError(mysql::Error),
+    RuntimeError(stdio::Error),
+    /// The TLS settings could not be used, such as a certificate that could not be read.
+    TlsError(String),
+    /// A MySQL script failed after the DDL statements before the failing one, which MySQL commits
+    /// as they run, may already have been applied.
+    PartiallyApplied(mysql::Error),
+    /// The database failed to take the migration lock, rather than finding it held.
+    LockError(String),
+}
+
+impl From<tokio_postgres::Error> for ConnectionError {
+    fn from(value: tokio_postgres::Error) -> Self {
+        Self::PostgresError(value)
+    }
 }
 
-impl From<postgres::Error> for ConnectionError {
-    fn from(value: postgres::Error) -> Self {
-        Self::PostgresError(value)
+impl From<mysql::Error> for ConnectionError {
+    fn from(value: mysql::Error) -> Self {
+        Self::MysqlError(value)
+    }
+}
+
+impl From<libsql::Error> for ConnectionError {
+    fn from(value: libsql::Error) -> Self {
+        Self::LibsqlError(value)
     }
 }
 
@@ -147,6 +211,15 @@ impl Display for ConnectionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let msg = match self {
             Self::PostgresError(err) => err.to_string(),
+            Self::LibsqlError(err) => err.to_string(),
+            Self::MysqlError(err) => err.to_string(),
+            Self::RuntimeError(err) => err.to_string(),
+            Self::TlsError(msg) => msg.clone(),
+            Self::PartiallyApplied(err) => format!(
+                "{err}. MySQL does not roll back DDL statements, so changes made before the \
+                 failing statement may still be applied"
+            ),
+            Self::LockError(msg) => msg.clone(),
         };
 
         f.write_str(&msg)
@@ -157,6 +230,12 @@ impl Error for ConnectionError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
             ConnectionError::PostgresError(error) => Some(error),
+            ConnectionError::LibsqlError(error) => Some(error),
+            ConnectionError::MysqlError(error) => Some(error),
+            ConnectionError::RuntimeError(error) => Some(error),
+            ConnectionError::TlsError(_) => None,
+            ConnectionError::PartiallyApplied(error) => Some(error),
+            ConnectionError::LockError(_) => None,
         }
     }
 }
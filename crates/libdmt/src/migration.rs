@@ -0,0 +1,151 @@
+use std::error::Error;
+
+use chrono::Utc;
+
+use crate::database::AsyncDatabaseConnection;
+use crate::io::MigrationSql;
+use crate::runner::Runner;
+use crate::{DatabaseConnection, Direction, MigrationError, MigrationId, PlannedMigration};
+
+/// A migration written in Rust, for changes that need more than SQL, such as backfills that call
+/// into application code.
+///
+/// Register it with [`Migrator::register`](crate::Migrator::register) under a
+/// `YYYYMMDDHHMMSS_name` version, and it runs in version order with the SQL migrations and is
+/// recorded in the same `migration` table. Unlike a SQL migration, it does not run in one
+/// transaction with its record: every statement it runs through `db` commits on its own.
+///
+/// Migrations must be `Send` and `Sync`, so that a [`Migrator`] holding them can be used from
+/// futures that move between threads.
+///
+/// [`Migrator`]: crate::Migrator
+pub trait Migration: Send + Sync {
+    fn up(&self, db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>>;
+    fn down(&self, db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// A migration of either kind, as run by a [`Migrator`](crate::Migrator).
+pub(crate) struct Definition<'a> {
+    pub(crate) id: MigrationId,
+    pub(crate) kind: Kind<'a>,
+}
+
+pub(crate) enum Kind<'a> {
+    Sql(MigrationSql),
+    Code(&'a dyn Migration),
+}
+
+impl Definition<'_> {
+    pub(crate) fn name(&self) -> String {
+        self.id.to_string()
+    }
+
+    /// The checksum recorded when the migration is run. Rust migrations have none.
+    pub(crate) fn checksum(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Sql(sql) => Some(sql.checksum()),
+            Kind::Code(_) => None,
+        }
+    }
+
+    pub(crate) fn transactional(&self) -> bool {
+        match &self.kind {
+            Kind::Sql(sql) => sql.transactional,
+            Kind::Code(_) => false,
+        }
+    }
+
+    pub(crate) fn plan(&self, direction: Direction) -> PlannedMigration {
+        let sql = match (&self.kind, direction) {
+            (Kind::Sql(sql), Direction::Up) => Some(sql.up.clone()),
+            (Kind::Sql(sql), Direction::Down) => Some(sql.down.clone()),
+            (Kind::Code(_), _) => None,
+        };
+
+        PlannedMigration {
+            name: self.name(),
+            direction,
+            sql,
+            transactional: self.transactional(),
+        }
+    }
+
+    /// Runs the migration in `direction`, and records or removes it in the `migration` table.
+    pub(crate) async fn execute(
+        &self,
+        db: &mut impl Runner,
+        direction: Direction,
+    ) -> Result<(), MigrationError> {
+        let name = self.name();
+        let now = Utc::now().naive_utc();
+
+        match (&self.kind, direction) {
+            (Kind::Sql(sql), Direction::Up) => {
+                let checksum = sql.checksum();
+                let db = db.connection();
+
+                if sql.transactional {
+                    db.apply_migration(&name, now, &checksum, &sql.up).await?;
+                } else {
+                    // If a statement fails, the statements before it stay applied and the
+                    // migration is not recorded, so it has to be cleaned up by hand before it is
+                    // run again.
+                    db.execute_sql_without_transaction(&sql.up).await?;
+                    db.create_migration(&name, now, Some(&checksum)).await?;
+                }
+            }
+            (Kind::Sql(sql), Direction::Down) => {
+                let db = db.connection();
+
+                if sql.transactional {
+                    db.revert_migration(&name, &sql.down).await?;
+                } else {
+                    db.execute_sql_without_transaction(&sql.down).await?;
+                    db.remove_migration_by_name(&name).await?;
+                }
+            }
+            (Kind::Code(migration), direction) => {
+                db.run_rust(*migration, direction).await.map_err(|source| {
+                    MigrationError::MigrationFailed {
+                        name: name.clone(),
+                        source,
+                    }
+                })?;
+
+                match direction {
+                    Direction::Up => {
+                        db.connection().create_migration(&name, now, None).await?;
+                    }
+                    Direction::Down => db.connection().remove_migration_by_name(&name).await?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that none of `definitions` are Rust migrations, which take a blocking connection and so
+/// cannot be run through an [`AsyncDatabaseConnection`].
+#[cfg(feature = "async")]
+pub(crate) fn check_async(definitions: &[&Definition]) -> Result<(), MigrationError> {
+    match definitions
+        .iter()
+        .find(|definition| matches!(definition.kind, Kind::Code(_)))
+    {
+        Some(definition) => Err(rust_migration_error(definition.name())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "async")]
+pub(crate) const RUST_MIGRATION_ERROR: &str =
+    "migrations written in Rust can only be run with a blocking connection";
+
+#[cfg(feature = "async")]
+fn rust_migration_error(name: String) -> MigrationError {
+    MigrationError::MigrationFailed {
+        name,
+        source: RUST_MIGRATION_ERROR.into(),
+    }
+}
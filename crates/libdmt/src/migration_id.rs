@@ -0,0 +1,125 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+
+use crate::MigrationError;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d%H%M%S";
+const TIMESTAMP_LEN: usize = 14;
+
+/// The version of a migration, parsed from its `YYYYMMDDHHMMSS_name` directory name.
+///
+/// Migrations are ordered by timestamp first and name second, which is the order they are run in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MigrationId {
+    timestamp: NaiveDateTime,
+    name: String,
+}
+
+impl MigrationId {
+    pub fn new(timestamp: NaiveDateTime, name: &str) -> Self {
+        Self {
+            timestamp,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn timestamp(&self) -> NaiveDateTime {
+        self.timestamp
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether `version` refers to this migration, either by its full `YYYYMMDDHHMMSS_name` or
+    /// by its timestamp alone.
+    pub fn matches(&self, version: &str) -> bool {
+        self.timestamp.format(TIMESTAMP_FORMAT).to_string() == version
+            || self.to_string() == version
+    }
+}
+
+impl FromStr for MigrationId {
+    type Err = MigrationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MigrationError::InvalidMigrationName(s.to_string());
+
+        let (timestamp, name) = s.split_once('_').ok_or_else(invalid)?;
+
+        if timestamp.len() != TIMESTAMP_LEN
+            || !timestamp.bytes().all(|b| b.is_ascii_digit())
+            || name.is_empty()
+        {
+            return Err(invalid());
+        }
+
+        let timestamp =
+            NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).map_err(|_| invalid())?;
+
+        Ok(Self::new(timestamp, name))
+    }
+}
+
+impl Display for MigrationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}_{}",
+            self.timestamp.format(TIMESTAMP_FORMAT),
+            self.name
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MigrationId;
+
+    #[test]
+    fn parses_and_displays_directory_names() {
+        let id: MigrationId = "20221014195913_add_vehicles_table".parse().unwrap();
+
+        assert_eq!(id.name(), "add_vehicles_table");
+        assert_eq!(id.to_string(), "20221014195913_add_vehicles_table");
+    }
+
+    #[test]
+    fn orders_by_timestamp_then_name() {
+        let mut ids: Vec<MigrationId> = [
+            "20221015155759_add_another_test_table",
+            "20221014195913_b",
+            "20221014195913_a",
+        ]
+        .iter()
+        .map(|name| name.parse().unwrap())
+        .collect();
+
+        ids.sort();
+
+        let names: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "20221014195913_a",
+                "20221014195913_b",
+                "20221015155759_add_another_test_table"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_names() {
+        for name in [
+            "add_vehicles_table",
+            "20221014195913",
+            "20221014195913_",
+            "2022101419591_short",
+            "20221314195913_bad_month",
+        ] {
+            assert!(name.parse::<MigrationId>().is_err(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::time::{Duration, Instant};
+
+use crate::commands::{plan_rollback, plan_run, rollback, run, status, verify};
+use crate::config::DEFAULT_LOCK_TIMEOUT;
+use crate::database::AsyncDatabaseConnection;
+use crate::io::MigrationSource;
+use crate::migration::{Definition, Kind};
+#[cfg(feature = "async")]
+use crate::runner::Async;
+use crate::runner::{block_on, Blocking, Runner};
+use crate::{
+    DatabaseConnection, HumanReporter, Migration, MigrationConfig, MigrationError, MigrationEvent,
+    MigrationReport, MigrationStatus, PlannedMigration, Reporter, RollbackTarget,
+};
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Runs and rolls back the migrations in a directory, or embedded with
+/// [`embed_migrations!`](crate::embed_migrations), together with any [`Migration`]s written in
+/// Rust that are registered with [`Migrator::register`].
+///
+/// The database's migration lock is held for the duration of every run and rollback, so that
+/// several processes migrating the same database at once take turns instead of racing.
+///
+/// Progress is sent to a [`Reporter`], which prints it for people to read unless another one is
+/// given with [`Migrator::reporter`]. The async methods need a reporter that is `Sync`, as their
+/// futures may move between threads.
+pub struct Migrator<R = HumanReporter> {
+    source: MigrationSource,
+    lock_timeout: Duration,
+    reporter: R,
+    migrations: Vec<(String, Box<dyn Migration>)>,
+}
+
+impl Migrator {
+    pub fn new(source: impl Into<MigrationSource>) -> Self {
+        Self {
+            source: source.into(),
+            lock_timeout: Duration::from_secs(DEFAULT_LOCK_TIMEOUT),
+            reporter: HumanReporter,
+            migrations: Vec::new(),
+        }
+    }
+
+    pub fn from_config(config: &MigrationConfig) -> Self {
+        Self::new(&config.migration_path).lock_timeout(Duration::from_secs(config.lock_timeout))
+    }
+}
+
+impl<R: Reporter> Migrator<R> {
+    /// How long to wait for another process to release the migration lock before giving up.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Where to send the progress of runs and rollbacks.
+    pub fn reporter<S: Reporter>(self, reporter: S) -> Migrator<S> {
+        Migrator {
+            source: self.source,
+            lock_timeout: self.lock_timeout,
+            reporter,
+            migrations: self.migrations,
+        }
+    }
+
+    /// Adds a migration written in Rust, under a `YYYYMMDDHHMMSS_name` version that orders it
+    /// among the other migrations.
+    pub fn register(mut self, version: &str, migration: impl Migration + 'static) -> Self {
+        self.migrations
+            .push((version.to_string(), Box::new(migration)));
+        self
+    }
+
+    /// Runs all outstanding migrations.
+    pub fn run<D: DatabaseConnection>(
+        &self,
+        db: &mut D,
+    ) -> Result<MigrationReport, MigrationError> {
+        block_on(self.run_with(&mut Blocking(db), None))
+    }
+
+    /// Runs the outstanding migrations up to and including the migration named by `target`.
+    pub fn run_to<D: DatabaseConnection>(
+        &self,
+        db: &mut D,
+        target: &str,
+    ) -> Result<MigrationReport, MigrationError> {
+        block_on(self.run_with(&mut Blocking(db), Some(target)))
+    }
+
+    /// Reverts the applied migrations that `target` selects, newest first.
+    pub fn rollback<D: DatabaseConnection>(
+        &self,
+        db: &mut D,
+        target: RollbackTarget,
+    ) -> Result<MigrationReport, MigrationError> {
+        block_on(self.rollback_with(&mut Blocking(db), target))
+    }
+
+    /// The async counterpart of [`Migrator::run`]. Migrations written in Rust take a blocking
+    /// connection, so this fails without running anything if one of them is pending.
+    #[cfg(feature = "async")]
+    pub async fn run_async<D: AsyncDatabaseConnection>(
+        &self,
+        db: &mut D,
+    ) -> Result<MigrationReport, MigrationError>
+    where
+        R: Sync,
+    {
+        self.run_with(&mut Async(db), None).await
+    }
+
+    /// The async counterpart of [`Migrator::run_to`].
+    #[cfg(feature = "async")]
+    pub async fn run_to_async<D: AsyncDatabaseConnection>(
+        &self,
+        db: &mut D,
+        target: &str,
+    ) -> Result<MigrationReport, MigrationError>
+    where
+        R: Sync,
+    {
+        self.run_with(&mut Async(db), Some(target)).await
+    }
+
+    /// The async counterpart of [`Migrator::rollback`]. Fails without reverting anything if one
+    /// of the migrations to revert is written in Rust.
+    #[cfg(feature = "async")]
+    pub async fn rollback_async<D: AsyncDatabaseConnection>(
+        &self,
+        db: &mut D,
+        target: RollbackTarget,
+    ) -> Result<MigrationReport, MigrationError>
+    where
+        R: Sync,
+    {
+        self.rollback_with(&mut Async(db), target).await
+    }
+
+    /// Returns the migrations [`Migrator::run`] would apply, without changing the database.
+    pub fn plan<D: DatabaseConnection>(
+        &self,
+        db: &mut D,
+    ) -> Result<Vec<PlannedMigration>, MigrationError> {
+        plan_run(db, &self.definitions()?, None)
+    }
+
+    /// Returns the migrations [`Migrator::run_to`] would apply, without changing the database.
+    pub fn plan_to<D: DatabaseConnection>(
+        &self,
+        db: &mut D,
+        target: &str,
+    ) -> Result<Vec<PlannedMigration>, MigrationError> {
+        plan_run(db, &self.definitions()?, Some(target))
+    }
+
+    /// Returns the migrations [`Migrator::rollback`] would revert, without changing the database.
+    pub fn plan_rollback<D: DatabaseConnection>(
+        &self,
+        db: &mut D,
+        target: RollbackTarget,
+    ) -> Result<Vec<PlannedMigration>, MigrationError> {
+        plan_rollback(db, &self.definitions()?, target)
+    }
+
+    /// Lists every migration, whether it has been run, and every applied migration that no
+    /// longer exists.
+    pub fn status<D: DatabaseConnection>(
+        &self,
+        db: &mut D,
+    ) -> Result<Vec<MigrationStatus>, MigrationError> {
+        status(db, &self.definitions()?)
+    }
+
+    /// Checks that none of the applied migrations have been changed since they were run.
+    pub fn verify<D: DatabaseConnection>(&self, db: &mut D) -> Result<(), MigrationError> {
+        verify(db, &self.definitions()?)
+    }
+
+    /// The migrations of the source and the registered Rust migrations, in the order they
+    /// should be run in.
+    fn definitions(&self) -> Result<Vec<Definition<'_>>, MigrationError> {
+        let mut definitions: Vec<Definition> = self
+            .source
+            .read_migrations()?
+            .into_iter()
+            .map(|(id, sql)| Definition {
+                id,
+                kind: Kind::Sql(sql),
+            })
+            .collect();
+
+        for (version, migration) in &self.migrations {
+            definitions.push(Definition {
+                id: version.parse()?,
+                kind: Kind::Code(migration.as_ref()),
+            });
+        }
+
+        definitions.sort_by(|a, b| a.id.cmp(&b.id));
+
+        if let Some(duplicate) = definitions.windows(2).find(|pair| pair[0].id == pair[1].id) {
+            return Err(MigrationError::DuplicateMigration(duplicate[0].name()));
+        }
+
+        Ok(definitions)
+    }
+
+    async fn run_with(
+        &self,
+        db: &mut impl Runner,
+        target: Option<&str>,
+    ) -> Result<MigrationReport, MigrationError> {
+        let definitions = self.definitions()?;
+
+        self.locked(db, async |db| {
+            run(db, &definitions, target, &self.reporter).await
+        })
+        .await
+    }
+
+    async fn rollback_with(
+        &self,
+        db: &mut impl Runner,
+        target: RollbackTarget,
+    ) -> Result<MigrationReport, MigrationError> {
+        let definitions = self.definitions()?;
+
+        self.locked(db, async |db| {
+            rollback(db, &definitions, target, &self.reporter).await
+        })
+        .await
+    }
+
+    async fn locked<D: Runner, T>(
+        &self,
+        db: &mut D,
+        f: impl AsyncFnOnce(&mut D) -> Result<T, MigrationError>,
+    ) -> Result<T, MigrationError> {
+        self.lock(db).await?;
+
+        let result = f(db).await;
+        let unlocked = db.connection().unlock().await;
+
+        let value = result?;
+        unlocked?;
+        Ok(value)
+    }
+
+    async fn lock(&self, db: &mut impl Runner) -> Result<(), MigrationError> {
+        let deadline = Instant::now() + self.lock_timeout;
+        let mut waiting = false;
+
+        while !db.connection().try_lock().await? {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(MigrationError::LockTimeout(self.lock_timeout));
+            }
+
+            if !waiting {
+                waiting = true;
+                self.reporter.report(&MigrationEvent::WaitingForLock);
+            }
+
+            db.sleep(LOCK_POLL_INTERVAL.min(deadline - now)).await;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::{ConnectionError, MigrationError};
+
+/// Whether a migration is being run or rolled back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// `"up"` or `"down"`, matching the name of the migration's SQL file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+        }
+    }
+}
+
+/// The migrations a run or rollback applied or reverted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub direction: Direction,
+    /// The names of the migrations, in the order they were applied or reverted. Empty if there
+    /// was nothing to do.
+    pub migrations: Vec<String>,
+}
+
+impl MigrationReport {
+    pub(crate) fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            migrations: Vec::new(),
+        }
+    }
+}
+
+/// Something that happened while connecting to the database, or running or rolling back
+/// migrations.
+#[derive(Debug)]
+pub enum MigrationEvent<'a> {
+    /// Connecting to the database failed, and will be tried again after `delay`.
+    ConnectionFailed {
+        /// The number of attempts that have failed so far.
+        attempt: u32,
+        delay: Duration,
+        error: &'a ConnectionError,
+    },
+    /// Another process holds the migration lock, and this one is waiting for it to be released.
+    WaitingForLock,
+    /// A rollback was requested, but no migrations have been run.
+    NothingToRollback,
+    Started {
+        name: &'a str,
+        direction: Direction,
+    },
+    Succeeded {
+        name: &'a str,
+        direction: Direction,
+        duration: Duration,
+    },
+    /// The migration failed. Unless it runs outside a transaction, its changes were rolled back.
+    Failed {
+        name: &'a str,
+        direction: Direction,
+        duration: Duration,
+        error: &'a MigrationError,
+    },
+}
+
+/// Receives the events of a [`Migrator`](crate::Migrator) as they happen.
+///
+/// Implement this to send progress somewhere other than stdout.
+pub trait Reporter {
+    fn report(&self, event: &MigrationEvent);
+}
+
+/// Lets a reporter be chosen at runtime, such as from a command line flag.
+impl<R: Reporter + ?Sized> Reporter for Box<R> {
+    fn report(&self, event: &MigrationEvent) {
+        (**self).report(event)
+    }
+}
+
+/// Prints the progress of a migration for people to read. This is the default reporter.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HumanReporter;
+
+impl Reporter for HumanReporter {
+    fn report(&self, event: &MigrationEvent) {
+        match event {
+            MigrationEvent::ConnectionFailed {
+                attempt,
+                delay,
+                error,
+            } => println!(
+                "   Could not connect to the database (attempt {attempt}): {error}. Retrying in \
+                 {:.1}s...",
+                delay.as_secs_f64()
+            ),
+            MigrationEvent::WaitingForLock => {
+                println!("   Waiting for another migration to finish...")
+            }
+            MigrationEvent::NothingToRollback => {
+                println!("   No migrations have yet been run. Thus, none can be rolled back. ")
+            }
+            MigrationEvent::Started { .. } => {}
+            MigrationEvent::Succeeded { name, .. } => println!("    SUCCESS: {name}"),
+            MigrationEvent::Failed { name, .. } => println!("    FAILURE: {name}"),
+        }
+    }
+}
+
+/// Prints every event as a single line JSON object, e.g.
+/// `{"event":"succeeded","name":"20240101000000_init","direction":"up","duration_ms":12}`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonReporter;
+
+impl JsonReporter {
+    fn to_json(event: &MigrationEvent) -> Value {
+        match event {
+            MigrationEvent::ConnectionFailed {
+                attempt,
+                delay,
+                error,
+            } => json!({
+                "event": "connection_failed",
+                "attempt": attempt,
+                "delay_ms": delay.as_millis() as u64,
+                "error": error.to_string(),
+            }),
+            MigrationEvent::WaitingForLock => json!({ "event": "waiting_for_lock" }),
+            MigrationEvent::NothingToRollback => json!({ "event": "nothing_to_rollback" }),
+            MigrationEvent::Started { name, direction } => json!({
+                "event": "started",
+                "name": name,
+                "direction": direction.as_str(),
+            }),
+            MigrationEvent::Succeeded {
+                name,
+                direction,
+                duration,
+            } => json!({
+                "event": "succeeded",
+                "name": name,
+                "direction": direction.as_str(),
+                "duration_ms": duration.as_millis() as u64,
+            }),
+            MigrationEvent::Failed {
+                name,
+                direction,
+                duration,
+                error,
+            } => json!({
+                "event": "failed",
+                "name": name,
+                "direction": direction.as_str(),
+                "duration_ms": duration.as_millis() as u64,
+                "error": error.to_string(),
+            }),
+        }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn report(&self, event: &MigrationEvent) {
+        println!("{}", Self::to_json(event));
+    }
+}
+
+/// Reports nothing. Errors are still returned to the caller.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QuietReporter;
+
+impl Reporter for QuietReporter {
+    fn report(&self, _event: &MigrationEvent) {}
+}
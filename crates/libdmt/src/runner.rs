@@ -0,0 +1,209 @@
+use std::error::Error;
+use std::future::{ready, Future};
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use crate::database::AsyncDatabaseConnection;
+use crate::migration::Definition;
+#[cfg(feature = "async")]
+use crate::migration::{check_async, RUST_MIGRATION_ERROR};
+use crate::{
+    ConnectionError, DatabaseConnection, Direction, Migration, MigrationError, MigrationRecord,
+};
+
+/// What runs and rollbacks are written against, so that the blocking and the async methods of
+/// [`Migrator`](crate::Migrator) share one implementation.
+pub(crate) trait Runner {
+    type Connection: AsyncDatabaseConnection;
+
+    fn connection(&mut self) -> &mut Self::Connection;
+
+    /// Checks that every one of `definitions` can be run, before any of them are.
+    fn check(&self, definitions: &[&Definition]) -> Result<(), MigrationError>;
+
+    fn run_rust(
+        &mut self,
+        migration: &dyn Migration,
+        direction: Direction,
+    ) -> impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send;
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+}
+
+/// Runs a blocking connection through the async implementation. Every future it returns is
+/// ready the first time it is polled, so [`block_on`] can drive them.
+pub(crate) struct Blocking<'a, D>(pub(crate) &'a mut D);
+
+impl<D: DatabaseConnection> Runner for Blocking<'_, D> {
+    type Connection = Self;
+
+    fn connection(&mut self) -> &mut Self {
+        self
+    }
+
+    fn check(&self, _definitions: &[&Definition]) -> Result<(), MigrationError> {
+        Ok(())
+    }
+
+    fn run_rust(
+        &mut self,
+        migration: &dyn Migration,
+        direction: Direction,
+    ) -> impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send {
+        ready(match direction {
+            Direction::Up => migration.up(self.0),
+            Direction::Down => migration.down(self.0),
+        })
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        thread::sleep(duration);
+        ready(())
+    }
+}
+
+impl<D: DatabaseConnection> AsyncDatabaseConnection for Blocking<'_, D> {
+    fn create_migrations_table(
+        &mut self,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.create_migrations_table())
+    }
+
+    fn upgrade_migrations_table(
+        &mut self,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.upgrade_migrations_table())
+    }
+
+    fn migration_table_exists(
+        &mut self,
+    ) -> impl Future<Output = Result<bool, ConnectionError>> + Send {
+        ready(self.0.migration_table_exists())
+    }
+
+    fn get_migrations(
+        &mut self,
+    ) -> impl Future<Output = Result<Vec<MigrationRecord>, ConnectionError>> + Send {
+        ready(self.0.get_migrations())
+    }
+
+    fn create_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: Option<&str>,
+    ) -> impl Future<Output = Result<MigrationRecord, ConnectionError>> + Send {
+        ready(self.0.create_migration(name, time, checksum))
+    }
+
+    fn execute_sql(
+        &mut self,
+        sql: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.execute_sql(sql))
+    }
+
+    fn execute_sql_without_transaction(
+        &mut self,
+        sql: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.execute_sql_without_transaction(sql))
+    }
+
+    fn query(
+        &mut self,
+        sql: &str,
+    ) -> impl Future<Output = Result<Vec<Vec<Option<String>>>, ConnectionError>> + Send {
+        ready(self.0.query(sql))
+    }
+
+    fn apply_migration(
+        &mut self,
+        name: &str,
+        time: NaiveDateTime,
+        checksum: &str,
+        sql: &str,
+    ) -> impl Future<Output = Result<MigrationRecord, ConnectionError>> + Send {
+        ready(self.0.apply_migration(name, time, checksum, sql))
+    }
+
+    fn revert_migration(
+        &mut self,
+        name: &str,
+        sql: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.revert_migration(name, sql))
+    }
+
+    fn remove_migration_by_id(
+        &mut self,
+        id: i32,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.remove_migration_by_id(id))
+    }
+
+    fn remove_migration_by_name(
+        &mut self,
+        name: &str,
+    ) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.remove_migration_by_name(name))
+    }
+
+    fn try_lock(&mut self) -> impl Future<Output = Result<bool, ConnectionError>> + Send {
+        ready(self.0.try_lock())
+    }
+
+    fn unlock(&mut self) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.unlock())
+    }
+
+    fn force_unlock(&mut self) -> impl Future<Output = Result<(), ConnectionError>> + Send {
+        ready(self.0.force_unlock())
+    }
+}
+
+/// Runs an async connection, which cannot run migrations written in Rust.
+#[cfg(feature = "async")]
+pub(crate) struct Async<'a, D>(pub(crate) &'a mut D);
+
+#[cfg(feature = "async")]
+impl<D: AsyncDatabaseConnection> Runner for Async<'_, D> {
+    type Connection = D;
+
+    fn connection(&mut self) -> &mut D {
+        self.0
+    }
+
+    fn check(&self, definitions: &[&Definition]) -> Result<(), MigrationError> {
+        check_async(definitions)
+    }
+
+    fn run_rust(
+        &mut self,
+        _migration: &dyn Migration,
+        _direction: Direction,
+    ) -> impl Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send {
+        ready(Err(RUST_MIGRATION_ERROR.into()))
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// Drives a future made from [`Blocking`] runners to completion.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("blocking connections never wait"),
+    }
+}
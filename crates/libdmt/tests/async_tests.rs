@@ -0,0 +1,114 @@
+#![cfg(feature = "async")]
+
+use std::error::Error;
+
+use libdmt::{
+    AsyncDatabaseConnection, DatabaseConnection, Direction, Migration, MigrationError, Migrator,
+    RollbackTarget,
+};
+
+mod common;
+
+use common::{sqlite_db_async, write_migration};
+
+struct Noop;
+
+impl Migration for Noop {
+    fn up(&self, _db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    fn down(&self, _db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn migrations_run_and_roll_back_asynchronously() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+    write_migration(
+        &migrations,
+        "20221015155759_vacuum",
+        "-- dmt:no-transaction\nVACUUM;",
+        "VACUUM;",
+    );
+
+    let mut db = sqlite_db_async(&dir.path().join("app.db")).await;
+
+    let report = libdmt::run_migrations_async(&mut db, &migrations)
+        .await
+        .unwrap();
+    assert_eq!(report.direction, Direction::Up);
+    assert_eq!(
+        report.migrations,
+        ["20221014195913_add_vehicles_table", "20221015155759_vacuum"]
+    );
+    db.execute_sql("INSERT INTO vehicle (id) VALUES (1);")
+        .await
+        .unwrap();
+
+    let report = libdmt::rollback_migrations_async(&mut db, &migrations, RollbackTarget::All)
+        .await
+        .unwrap();
+    assert_eq!(
+        report.migrations,
+        ["20221015155759_vacuum", "20221014195913_add_vehicles_table"]
+    );
+    assert!(db.get_migrations().await.unwrap().is_empty());
+    assert!(db.query("SELECT * FROM vehicle;").await.is_err());
+}
+
+#[tokio::test]
+async fn migrations_can_run_on_a_spawned_task() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let path = dir.path().join("app.db");
+    let migrator = Migrator::new(&migrations);
+
+    let report = tokio::spawn(async move {
+        let mut db = sqlite_db_async(&path).await;
+        migrator.run_async(&mut db).await
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(report.migrations, ["20221014195913_add_vehicles_table"]);
+}
+
+#[tokio::test]
+async fn rust_migrations_are_not_run_asynchronously() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut db = sqlite_db_async(&dir.path().join("app.db")).await;
+
+    let result = Migrator::new(&migrations)
+        .register("20221016000000_noop", Noop)
+        .run_async(&mut db)
+        .await;
+    assert!(matches!(
+        result,
+        Err(MigrationError::MigrationFailed { name, .. }) if name == "20221016000000_noop"
+    ));
+    assert!(db.get_migrations().await.unwrap().is_empty());
+}
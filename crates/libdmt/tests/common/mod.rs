@@ -0,0 +1,42 @@
+//! Fixtures shared by the integration tests, each of which uses only some of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(feature = "async")]
+use libdmt::AsyncMigrationDatabase;
+use libdmt::{DmtConfig, MigrationDatabase};
+
+fn sqlite_config(path: &Path) -> DmtConfig {
+    DmtConfig::from_str(&format!(
+        r#"
+[connection]
+database = "sqlite"
+
+[connection.sqlite]
+path = "{}"
+"#,
+        path.display()
+    ))
+    .unwrap()
+}
+
+pub fn sqlite_db(path: &Path) -> MigrationDatabase {
+    MigrationDatabase::try_from(&sqlite_config(path)).unwrap()
+}
+
+#[cfg(feature = "async")]
+pub async fn sqlite_db_async(path: &Path) -> AsyncMigrationDatabase {
+    AsyncMigrationDatabase::connect(&sqlite_config(path))
+        .await
+        .unwrap()
+}
+
+pub fn write_migration(root: &Path, name: &str, up: &str, down: &str) {
+    let dir = root.join(name);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("up.sql"), up).unwrap();
+    fs::write(dir.join("down.sql"), down).unwrap();
+}
@@ -1,6 +1,28 @@
-//use libdmt::migrate;
+use std::env;
 
-//#[test]
-//fn migrate_macro_works() {
-//    migrate!();
-//}
+use libdmt::{migrate, migrate_or_panic, ConfigError, Direction, DmtError};
+
+#[test]
+fn migrate_macro_returns_a_report() {
+    let dir = tempfile::tempdir().unwrap();
+    env::set_var("DMT_TEST_DATABASE", dir.path().join("app.db"));
+
+    let report = migrate!("tests/dmt.config.toml").unwrap();
+    assert_eq!(report.direction, Direction::Up);
+    assert_eq!(
+        report.migrations,
+        [
+            "20221014195913_add_vehicles_table",
+            "20221015155759_add_another_test_table"
+        ]
+    );
+
+    let report = migrate_or_panic!("tests/dmt.config.toml");
+    assert!(report.migrations.is_empty());
+
+    let result = migrate!("tests/dmt.config.toml", profile = "missing");
+    assert!(matches!(
+        result,
+        Err(DmtError::ConfigError(ConfigError::UnknownProfile(name))) if name == "missing"
+    ));
+}
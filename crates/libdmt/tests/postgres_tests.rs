@@ -0,0 +1,90 @@
+//! These run against the `db` service of `development/docker-compose.yml`:
+//!
+//! ```sh
+//! docker compose -f development/docker-compose.yml up -d db
+//! cargo test -p libdmt --test postgres_tests -- --ignored
+//! ```
+
+use std::str::FromStr;
+
+use libdmt::{DatabaseConnection, DmtConfig, MigrationDatabase, Migrator, RollbackTarget};
+
+mod common;
+
+use common::write_migration;
+
+fn postgres_db(table: &str) -> MigrationDatabase {
+    let config = DmtConfig::from_str(&format!(
+        r#"
+[migration]
+table = "{table}"
+
+[connection]
+database = "postgres"
+
+[connection.postgres]
+connectionString = "host=localhost user=postgres password=dev dbname=dmt_dev"
+"#
+    ))
+    .unwrap();
+
+    MigrationDatabase::try_from(&config).unwrap()
+}
+
+#[test]
+#[ignore = "needs the db service of docker-compose.yml"]
+fn tables_with_different_names_have_their_own_locks() {
+    let mut first = postgres_db("lock_test_first");
+    let mut second = postgres_db("lock_test_second");
+    let mut other_first = postgres_db("lock_test_first");
+
+    assert!(first.try_lock().unwrap());
+    assert!(second.try_lock().unwrap());
+    assert!(!other_first.try_lock().unwrap());
+
+    // Forcing the lock of one table open ends the session that holds it, and no other.
+    other_first.force_unlock().unwrap();
+    assert!(second.query("SELECT 1").is_ok());
+    assert!(other_first.try_lock().unwrap());
+
+    second.unlock().unwrap();
+    other_first.unlock().unwrap();
+}
+
+#[test]
+#[ignore = "needs the db service of docker-compose.yml"]
+fn indexes_are_created_concurrently_outside_a_transaction() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE concurrent_vehicle (id INT PRIMARY KEY, name TEXT);",
+        "DROP TABLE concurrent_vehicle;",
+    );
+    // Postgres refuses to build or drop an index concurrently inside a transaction.
+    write_migration(
+        &migrations,
+        "20221015155759_index_vehicle_names",
+        "-- dmt:no-transaction\nCREATE INDEX CONCURRENTLY concurrent_vehicle_name ON concurrent_vehicle (name);",
+        "-- dmt:no-transaction\nDROP INDEX CONCURRENTLY concurrent_vehicle_name;",
+    );
+
+    let mut db = postgres_db("concurrent_index_test");
+    let migrator = Migrator::new(&migrations);
+    let index =
+        "SELECT indexname::text FROM pg_indexes WHERE indexname = 'concurrent_vehicle_name'";
+
+    let report = migrator.run(&mut db).unwrap();
+    assert_eq!(report.migrations.len(), 2);
+    assert_eq!(
+        db.query(index).unwrap(),
+        [[Some("concurrent_vehicle_name".to_string())]]
+    );
+
+    let report = migrator.rollback(&mut db, RollbackTarget::All).unwrap();
+    assert_eq!(report.migrations.len(), 2);
+    assert!(db.query(index).unwrap().is_empty());
+
+    db.execute_sql("DROP TABLE concurrent_index_test;").unwrap();
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "native-tls")]
+
+//! These run against the `db-tls` service of `development/docker-compose.yml`, with the
+//! certificates made by `development/tls/generate-certs.sh`:
+//!
+//! ```sh
+//! development/tls/generate-certs.sh
+//! docker compose -f development/docker-compose.yml up -d db-tls
+//! cargo test -p libdmt --features native-tls --test postgres_tls_tests -- --ignored
+//! ```
+
+use std::str::FromStr;
+
+use libdmt::{DatabaseConnection, DmtConfig, MigrationDatabase};
+
+fn tls_config(mode: &str, with_ca_file: bool) -> DmtConfig {
+    let certs = concat!(env!("CARGO_MANIFEST_DIR"), "/../../development/tls");
+    let ca_file = if with_ca_file {
+        format!(r#"caFile = "{certs}/ca.crt""#)
+    } else {
+        String::new()
+    };
+
+    DmtConfig::from_str(&format!(
+        r#"
+[connection]
+database = "postgres"
+
+[connection.postgres]
+connectionString = "host=localhost port=5433 user=postgres password=dev dbname=dmt_dev"
+
+[connection.postgres.tls]
+mode = "{mode}"
+{ca_file}
+"#
+    ))
+    .unwrap()
+}
+
+#[test]
+#[ignore = "needs the db-tls service of docker-compose.yml"]
+fn verify_full_connects_with_the_development_ca() {
+    let mut db = MigrationDatabase::try_from(&tls_config("verify-full", true)).unwrap();
+
+    assert_eq!(
+        db.query("SELECT ssl::text FROM pg_stat_ssl WHERE pid = pg_backend_pid()")
+            .unwrap(),
+        [[Some("true".to_string())]]
+    );
+}
+
+#[test]
+#[ignore = "needs the db-tls service of docker-compose.yml"]
+fn require_connects_without_a_ca_file() {
+    let mut db = MigrationDatabase::try_from(&tls_config("require", false)).unwrap();
+
+    db.migration_table_exists().unwrap();
+}
+
+#[test]
+#[ignore = "needs the db-tls service of docker-compose.yml"]
+fn verify_full_rejects_a_certificate_it_cannot_check() {
+    assert!(MigrationDatabase::try_from(&tls_config("verify-full", false)).is_err());
+}
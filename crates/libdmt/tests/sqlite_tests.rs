@@ -0,0 +1,982 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
+use libdmt::{
+    embed_migrations, DatabaseConnection, Direction, DmtConfig, DmtError, EmbeddedMigrations,
+    Migration, MigrationDatabase, MigrationError, MigrationEvent, MigrationState, Migrator,
+    PlannedMigration, Reporter, RollbackTarget,
+};
+
+mod common;
+
+use common::{sqlite_db, write_migration};
+
+fn migration_names(db: &mut impl DatabaseConnection) -> Vec<String> {
+    let mut names: Vec<String> = db
+        .get_migrations()
+        .unwrap()
+        .into_iter()
+        .map(|migration| migration.name)
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn missing_config_section_is_an_error() {
+    let config = DmtConfig::from_str(
+        r#"
+[connection]
+database = "sqlite"
+"#,
+    )
+    .unwrap();
+
+    assert!(MigrationDatabase::try_from(&config).is_err());
+}
+
+#[test]
+fn migrations_persist_in_database_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("app.db");
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY, name VARCHAR(255));",
+        "DROP TABLE vehicle;",
+    );
+    write_migration(
+        &migrations,
+        "20221015155759_add_another_test_table",
+        "CREATE TABLE another_test (id INTEGER PRIMARY KEY);",
+        "DROP TABLE another_test;",
+    );
+
+    let mut db = sqlite_db(&db_path);
+    assert!(!db.migration_table_exists().unwrap());
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    drop(db);
+
+    assert!(db_path.exists());
+
+    let mut db = sqlite_db(&db_path);
+    assert!(db.migration_table_exists().unwrap());
+    assert_eq!(
+        migration_names(&mut db),
+        vec![
+            "20221014195913_add_vehicles_table",
+            "20221015155759_add_another_test_table"
+        ]
+    );
+
+    // Running again is a no-op.
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    assert_eq!(migration_names(&mut db).len(), 2);
+
+    db.execute_sql("INSERT INTO vehicle (name) VALUES ('bike');")
+        .unwrap();
+}
+
+#[test]
+fn failed_migration_is_not_recorded() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_broken",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY); INSERT INTO missing VALUES (1);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    assert!(libdmt::run_migrations(&mut db, &migrations).is_err());
+
+    assert!(migration_names(&mut db).is_empty());
+    db.execute_sql("CREATE TABLE vehicle (id INTEGER PRIMARY KEY);")
+        .unwrap();
+}
+
+#[test]
+fn rollback_removes_migrations() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::default()).unwrap();
+
+    assert!(migration_names(&mut db).is_empty());
+    assert!(db.execute_sql("SELECT * FROM vehicle;").is_err());
+}
+
+#[test]
+fn status_reports_applied_pending_and_missing() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    let statuses = libdmt::migration_status(&mut db, &migrations).unwrap();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].state, MigrationState::Pending);
+
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    fs::remove_dir_all(migrations.join("20221014195913_add_vehicles_table")).unwrap();
+    write_migration(
+        &migrations,
+        "20221015155759_add_another_test_table",
+        "CREATE TABLE another_test (id INTEGER PRIMARY KEY);",
+        "DROP TABLE another_test;",
+    );
+
+    let statuses = libdmt::migration_status(&mut db, &migrations).unwrap();
+    assert_eq!(statuses.len(), 2);
+    assert_eq!(statuses[0].name, "20221014195913_add_vehicles_table");
+    assert!(matches!(statuses[0].state, MigrationState::Missing(_)));
+    assert_eq!(statuses[1].name, "20221015155759_add_another_test_table");
+    assert_eq!(statuses[1].state, MigrationState::Pending);
+}
+
+#[test]
+fn rollback_reverts_newest_migrations_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    for (name, table) in [
+        ("20221014195913_add_vehicles_table", "vehicle"),
+        ("20221015155759_add_owners_table", "owner"),
+        ("20221016101010_add_colours_table", "colour"),
+    ] {
+        write_migration(
+            &migrations,
+            name,
+            &format!("CREATE TABLE {table} (id INTEGER PRIMARY KEY);"),
+            &format!("DROP TABLE {table};"),
+        );
+    }
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+
+    let mut applied = db.get_migrations().unwrap();
+    applied.sort_by_key(|migration| migration.id);
+
+    libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::default()).unwrap();
+    let remaining = migration_names(&mut db);
+    assert_eq!(remaining.len(), 2);
+    assert!(!remaining.contains(&applied[2].name));
+
+    libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::Steps(5)).unwrap();
+    assert!(migration_names(&mut db).is_empty());
+
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    assert_eq!(migration_names(&mut db).len(), 3);
+
+    libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::All).unwrap();
+    assert!(migration_names(&mut db).is_empty());
+    assert!(db.execute_sql("SELECT * FROM vehicle;").is_err());
+}
+
+#[test]
+fn rollback_reverts_in_the_order_migrations_were_applied() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221015155759_add_owners_table",
+        "CREATE TABLE owner (id INTEGER PRIMARY KEY);",
+        "DROP TABLE owner;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+
+    // An older version that is only applied after a newer one, e.g. from a merged branch.
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+
+    let report =
+        libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::default()).unwrap();
+    assert_eq!(report.migrations, ["20221014195913_add_vehicles_table"]);
+    assert_eq!(
+        migration_names(&mut db),
+        ["20221015155759_add_owners_table"]
+    );
+}
+
+#[test]
+fn migrations_run_in_version_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221015155759_add_vehicle_owner",
+        "ALTER TABLE vehicle ADD COLUMN owner VARCHAR(255);",
+        "ALTER TABLE vehicle DROP COLUMN owner;",
+    );
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+
+    let mut applied = db.get_migrations().unwrap();
+    applied.sort_by_key(|migration| migration.id);
+    assert_eq!(applied[0].name, "20221014195913_add_vehicles_table");
+    assert_eq!(applied[1].name, "20221015155759_add_vehicle_owner");
+}
+
+#[test]
+fn malformed_migration_directory_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    let result = libdmt::run_migrations(&mut db, &migrations);
+
+    assert!(matches!(
+        result,
+        Err(MigrationError::InvalidMigrationName(name)) if name == "add_vehicles_table"
+    ));
+}
+
+#[test]
+fn migrate_and_rollback_to_target_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    for (name, table) in [
+        ("20221014195913_add_vehicles_table", "vehicle"),
+        ("20221015155759_add_owners_table", "owner"),
+        ("20221016101010_add_colours_table", "colour"),
+    ] {
+        write_migration(
+            &migrations,
+            name,
+            &format!("CREATE TABLE {table} (id INTEGER PRIMARY KEY);"),
+            &format!("DROP TABLE {table};"),
+        );
+    }
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    libdmt::run_migrations_to(&mut db, &migrations, "20221015155759_add_owners_table").unwrap();
+    assert_eq!(
+        migration_names(&mut db),
+        vec![
+            "20221014195913_add_vehicles_table",
+            "20221015155759_add_owners_table"
+        ]
+    );
+
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    assert_eq!(migration_names(&mut db).len(), 3);
+
+    libdmt::rollback_migrations(
+        &mut db,
+        &migrations,
+        RollbackTarget::To("20221014195913".to_string()),
+    )
+    .unwrap();
+    assert_eq!(
+        migration_names(&mut db),
+        vec!["20221014195913_add_vehicles_table"]
+    );
+
+    let result = libdmt::run_migrations_to(&mut db, &migrations, "20991231235959_unknown");
+    assert!(matches!(result, Err(MigrationError::UnknownTarget(_))));
+    assert_eq!(migration_names(&mut db).len(), 1);
+}
+
+#[test]
+fn changed_migration_files_are_detected() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    libdmt::verify_migrations(&mut db, &migrations).unwrap();
+
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY, name VARCHAR(255));",
+        "DROP TABLE vehicle;",
+    );
+    write_migration(
+        &migrations,
+        "20221015155759_add_owners_table",
+        "CREATE TABLE owner (id INTEGER PRIMARY KEY);",
+        "DROP TABLE owner;",
+    );
+
+    let expected = vec!["20221014195913_add_vehicles_table".to_string()];
+    assert!(matches!(
+        libdmt::verify_migrations(&mut db, &migrations),
+        Err(MigrationError::ChecksumMismatch(names)) if names == expected
+    ));
+    assert!(matches!(
+        libdmt::run_migrations(&mut db, &migrations),
+        Err(MigrationError::ChecksumMismatch(names)) if names == expected
+    ));
+    assert_eq!(migration_names(&mut db).len(), 1);
+}
+
+#[test]
+fn migration_table_without_checksums_is_upgraded() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+    write_migration(
+        &migrations,
+        "20221015155759_add_owners_table",
+        "CREATE TABLE owner (id INTEGER PRIMARY KEY);",
+        "DROP TABLE owner;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    db.execute_sql(
+        "CREATE TABLE migration (
+            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            time TIMESTAMP NOT NULL
+        );
+        CREATE TABLE vehicle (id INTEGER PRIMARY KEY);
+        INSERT INTO migration (name, time)
+            VALUES ('20221014195913_add_vehicles_table', '2022-10-14 19:59:13');",
+    )
+    .unwrap();
+
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    libdmt::verify_migrations(&mut db, &migrations).unwrap();
+
+    let mut applied = db.get_migrations().unwrap();
+    applied.sort_by_key(|migration| migration.id);
+    assert_eq!(applied.len(), 2);
+    assert_eq!(applied[0].checksum, None);
+    assert!(applied[1].checksum.is_some());
+}
+
+#[test]
+fn migration_table_can_be_renamed() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_migration_table",
+        "CREATE TABLE migration (id INTEGER PRIMARY KEY, species TEXT NOT NULL);",
+        "DROP TABLE migration;",
+    );
+
+    let config = DmtConfig::from_str(&format!(
+        r#"
+[migration]
+migrationPath = "{}"
+table = 'dmt "history"'
+
+[connection]
+database = "sqlite"
+
+[connection.sqlite]
+path = "{}"
+"#,
+        migrations.display(),
+        dir.path().join("app.db").display()
+    ))
+    .unwrap();
+    let mut db = MigrationDatabase::try_from(&config).unwrap();
+
+    Migrator::from_config(&config.migration)
+        .run(&mut db)
+        .unwrap();
+
+    assert!(db.migration_table_exists().unwrap());
+    assert_eq!(
+        migration_names(&mut db),
+        ["20221014195913_add_migration_table"]
+    );
+    assert_eq!(
+        db.query(r#"SELECT name FROM "dmt ""history""""#).unwrap(),
+        [[Some("20221014195913_add_migration_table".to_string())]]
+    );
+    assert!(db.query("SELECT * FROM migration").unwrap().is_empty());
+
+    libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::default()).unwrap();
+    assert!(migration_names(&mut db).is_empty());
+    assert!(db.query("SELECT * FROM migration").is_err());
+}
+
+#[test]
+fn migration_and_record_are_committed_together() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    db.create_migrations_table().unwrap();
+
+    let time = chrono::Utc::now().naive_utc();
+    let up_sql = "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);
+        INSERT INTO migration (name, time) VALUES ('20221014195913_add_vehicles_table', '');";
+
+    // Recording the migration conflicts with the row inserted by the SQL itself.
+    let result = db.apply_migration("20221014195913_add_vehicles_table", time, "", up_sql);
+    assert!(result.is_err());
+
+    assert!(migration_names(&mut db).is_empty());
+    db.execute_sql("CREATE TABLE vehicle (id INTEGER PRIMARY KEY);")
+        .unwrap();
+
+    db.revert_migration("20221014195913_add_vehicles_table", "DROP TABLE vehicle;")
+        .unwrap();
+    assert!(db.execute_sql("SELECT * FROM vehicle;").is_err());
+}
+
+#[test]
+fn migrations_wait_for_the_migration_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("app.db");
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let mut other = sqlite_db(&db_path);
+    assert!(other.try_lock().unwrap());
+
+    let mut db = sqlite_db(&db_path);
+    let migrator = Migrator::new(&migrations).lock_timeout(Duration::from_millis(100));
+    assert!(matches!(
+        migrator.run(&mut db),
+        Err(MigrationError::LockTimeout(_))
+    ));
+    assert!(!db.migration_table_exists().unwrap());
+
+    db.force_unlock().unwrap();
+    migrator.run(&mut db).unwrap();
+    assert_eq!(migration_names(&mut db).len(), 1);
+
+    // The lock is released once the migrations have run.
+    assert!(other.try_lock().unwrap());
+    other.unlock().unwrap();
+}
+
+/// Records the events it is sent, as `(kind, name)` pairs.
+#[derive(Clone, Default)]
+struct RecordingReporter(Rc<RefCell<Vec<(String, String)>>>);
+
+impl Reporter for RecordingReporter {
+    fn report(&self, event: &MigrationEvent) {
+        let attempt;
+        let (kind, name) = match event {
+            MigrationEvent::ConnectionFailed { attempt: n, .. } => {
+                attempt = n.to_string();
+                ("connection_failed", attempt.as_str())
+            }
+            MigrationEvent::WaitingForLock => ("waiting", ""),
+            MigrationEvent::NothingToRollback => ("nothing", ""),
+            MigrationEvent::Started { name, .. } => ("started", *name),
+            MigrationEvent::Succeeded { name, .. } => ("succeeded", *name),
+            MigrationEvent::Failed { name, .. } => ("failed", *name),
+        };
+
+        self.0
+            .borrow_mut()
+            .push((kind.to_string(), name.to_string()));
+    }
+}
+
+#[test]
+fn migrations_report_to_the_given_reporter() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+    write_migration(
+        &migrations,
+        "20221015155759_broken",
+        "CREATE TABLE broken (id INTEGER PRIMARY KEY",
+        "",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    let reporter = RecordingReporter::default();
+    let migrator = Migrator::new(&migrations).reporter(reporter.clone());
+
+    migrator.rollback(&mut db, RollbackTarget::All).unwrap();
+    assert!(migrator.run(&mut db).is_err());
+    migrator.rollback(&mut db, RollbackTarget::All).unwrap();
+
+    let events = reporter.0.borrow();
+    let events: Vec<(&str, &str)> = events
+        .iter()
+        .map(|(kind, name)| (kind.as_str(), name.as_str()))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            ("nothing", ""),
+            ("started", "20221014195913_add_vehicles_table"),
+            ("succeeded", "20221014195913_add_vehicles_table"),
+            ("started", "20221015155759_broken"),
+            ("failed", "20221015155759_broken"),
+            ("started", "20221014195913_add_vehicles_table"),
+            ("succeeded", "20221014195913_add_vehicles_table"),
+        ]
+    );
+}
+
+#[test]
+fn permanent_connection_errors_are_not_retried() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = DmtConfig::from_str(&format!(
+        r#"
+[connection]
+database = "sqlite"
+
+[connection.sqlite]
+path = "{}"
+
+[connection.retry]
+maxAttempts = 3
+initialDelay = 0
+"#,
+        dir.path().join("missing").join("app.db").display()
+    ))
+    .unwrap();
+
+    let reporter = RecordingReporter::default();
+    let result = MigrationDatabase::connect_with_reporter(&config, &reporter);
+    assert!(matches!(
+        result,
+        Err(DmtError::MigrationError(MigrationError::ConnectionError(_)))
+    ));
+
+    assert!(reporter.0.borrow().is_empty());
+}
+
+#[test]
+fn plans_do_not_change_the_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    for (name, table) in [
+        ("20221014195913_add_vehicles_table", "vehicle"),
+        ("20221015155759_add_owners_table", "owner"),
+    ] {
+        write_migration(
+            &migrations,
+            name,
+            &format!("CREATE TABLE {table} (id INTEGER PRIMARY KEY);"),
+            &format!("DROP TABLE {table};"),
+        );
+    }
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    let migrator = Migrator::new(&migrations);
+
+    let plan = migrator.plan(&mut db).unwrap();
+    assert!(!db.migration_table_exists().unwrap());
+    assert_eq!(
+        plan,
+        [
+            PlannedMigration {
+                name: "20221014195913_add_vehicles_table".to_string(),
+                direction: Direction::Up,
+                sql: Some("CREATE TABLE vehicle (id INTEGER PRIMARY KEY);".to_string()),
+                transactional: true,
+            },
+            PlannedMigration {
+                name: "20221015155759_add_owners_table".to_string(),
+                direction: Direction::Up,
+                sql: Some("CREATE TABLE owner (id INTEGER PRIMARY KEY);".to_string()),
+                transactional: true,
+            },
+        ]
+    );
+    assert!(migrator
+        .plan_rollback(&mut db, RollbackTarget::All)
+        .unwrap()
+        .is_empty());
+
+    migrator.run_to(&mut db, "20221014195913").unwrap();
+
+    let names = |plan: Vec<PlannedMigration>| -> Vec<String> {
+        plan.into_iter().map(|migration| migration.name).collect()
+    };
+    assert_eq!(
+        names(migrator.plan(&mut db).unwrap()),
+        ["20221015155759_add_owners_table"]
+    );
+
+    migrator.run(&mut db).unwrap();
+
+    let plan = migrator
+        .plan_rollback(&mut db, RollbackTarget::All)
+        .unwrap();
+    assert_eq!(plan[0].direction, Direction::Down);
+    assert_eq!(plan[0].sql.as_deref(), Some("DROP TABLE owner;"));
+    assert_eq!(
+        names(plan),
+        [
+            "20221015155759_add_owners_table",
+            "20221014195913_add_vehicles_table"
+        ]
+    );
+    assert_eq!(migration_names(&mut db).len(), 2);
+}
+
+#[test]
+fn plans_and_status_read_a_migration_table_without_checksums() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    for (name, table) in [
+        ("20221014195913_add_vehicles_table", "vehicle"),
+        ("20221015155759_add_owners_table", "owner"),
+    ] {
+        write_migration(
+            &migrations,
+            name,
+            &format!("CREATE TABLE {table} (id INTEGER PRIMARY KEY);"),
+            &format!("DROP TABLE {table};"),
+        );
+    }
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    db.execute_sql(
+        "CREATE TABLE migration (
+            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            time TIMESTAMP NOT NULL
+        );
+        CREATE TABLE vehicle (id INTEGER PRIMARY KEY);
+        INSERT INTO migration (name, time)
+            VALUES ('20221014195913_add_vehicles_table', '2022-10-14 19:59:13');",
+    )
+    .unwrap();
+
+    let migrator = Migrator::new(&migrations);
+    let names = |plan: Vec<PlannedMigration>| -> Vec<String> {
+        plan.into_iter().map(|migration| migration.name).collect()
+    };
+    assert_eq!(
+        names(migrator.plan(&mut db).unwrap()),
+        ["20221015155759_add_owners_table"]
+    );
+    assert_eq!(
+        names(
+            migrator
+                .plan_rollback(&mut db, RollbackTarget::All)
+                .unwrap()
+        ),
+        ["20221014195913_add_vehicles_table"]
+    );
+    assert_eq!(migrator.status(&mut db).unwrap().len(), 2);
+    migrator.verify(&mut db).unwrap();
+
+    // None of these change the database, so the table is only upgraded by the next run.
+    let columns = db
+        .query("SELECT name FROM pragma_table_info('migration') WHERE name = 'checksum'")
+        .unwrap();
+    assert!(columns.is_empty());
+}
+
+#[test]
+fn single_file_migrations_run_alongside_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+    fs::write(
+        migrations.join("20221015155759_add_owners_table.sql"),
+        "-- Owners of vehicles\n\
+         -- dmt:up\n\
+         CREATE TABLE owner (id INTEGER PRIMARY KEY);\n\
+         \n\
+         -- dmt:down\n\
+         DROP TABLE owner;\n",
+    )
+    .unwrap();
+    libdmt::new_single_file_migration("add_colours_table", &migrations).unwrap();
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    let migrator = Migrator::new(&migrations);
+
+    let plan = migrator.plan(&mut db).unwrap();
+    assert_eq!(plan.len(), 3);
+    assert_eq!(
+        plan[1].sql.as_deref(),
+        Some("CREATE TABLE owner (id INTEGER PRIMARY KEY);\n\n")
+    );
+    assert!(plan[2].name.ends_with("_add_colours_table"));
+
+    migrator.run(&mut db).unwrap();
+    assert_eq!(migration_names(&mut db).len(), 3);
+    db.execute_sql("INSERT INTO owner (id) VALUES (1);")
+        .unwrap();
+    libdmt::verify_migrations(&mut db, &migrations).unwrap();
+
+    migrator
+        .rollback(&mut db, RollbackTarget::Steps(2))
+        .unwrap();
+    assert_eq!(
+        migration_names(&mut db),
+        vec!["20221014195913_add_vehicles_table"]
+    );
+    assert!(db.execute_sql("SELECT * FROM owner;").is_err());
+}
+
+#[test]
+fn malformed_single_file_migrations_are_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    fs::create_dir_all(&migrations).unwrap();
+    fs::write(
+        migrations.join("20221014195913_no_markers.sql"),
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+    )
+    .unwrap();
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    let result = libdmt::run_migrations(&mut db, &migrations);
+    assert!(matches!(
+        result,
+        Err(MigrationError::MalformedMigration { name, .. }) if name == "20221014195913_no_markers"
+    ));
+
+    write_migration(&migrations, "20221014195913_no_markers", "", "");
+    let result = libdmt::run_migrations(&mut db, &migrations);
+    assert!(matches!(result, Err(MigrationError::DuplicateMigration(_))));
+}
+
+#[test]
+fn no_transaction_migrations_run_outside_a_transaction() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+    // SQLite refuses to VACUUM inside a transaction.
+    write_migration(
+        &migrations,
+        "20221015155759_vacuum",
+        "-- Reclaim space\n-- dmt:no-transaction\nVACUUM;",
+        "VACUUM;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    assert_eq!(migration_names(&mut db).len(), 2);
+
+    let statuses = libdmt::migration_status(&mut db, &migrations).unwrap();
+    let transactional: Vec<bool> = statuses.iter().map(|status| status.transactional).collect();
+    assert_eq!(transactional, [true, false]);
+
+    libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::Steps(1)).unwrap();
+    assert_eq!(
+        migration_names(&mut db),
+        vec!["20221014195913_add_vehicles_table"]
+    );
+
+    // Outside a transaction, the statements before a failing one stay applied, and the
+    // migration is not recorded.
+    fs::write(
+        migrations.join("20221015155759_vacuum/up.sql"),
+        "-- dmt:no-transaction\nCREATE TABLE partial (id INTEGER);\nINSERT INTO missing VALUES (1);",
+    )
+    .unwrap();
+    assert!(libdmt::run_migrations(&mut db, &migrations).is_err());
+    assert_eq!(migration_names(&mut db).len(), 1);
+    db.execute_sql("SELECT * FROM partial;").unwrap();
+}
+
+/// Copies every vehicle's name into a new `slug` column, a row at a time.
+struct BackfillSlugs;
+
+impl Migration for BackfillSlugs {
+    fn up(&self, db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        db.execute_sql("ALTER TABLE vehicle ADD COLUMN slug VARCHAR(255);")?;
+
+        for row in db.query("SELECT id, name FROM vehicle ORDER BY id;")? {
+            let (id, name) = (row[0].as_deref().unwrap(), row[1].as_deref().unwrap());
+            db.execute_sql(&format!(
+                "UPDATE vehicle SET slug = '{}' WHERE id = {id};",
+                name.to_lowercase().replace(' ', "-")
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    fn down(&self, db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        db.execute_sql("ALTER TABLE vehicle DROP COLUMN slug;")?;
+
+        Ok(())
+    }
+}
+
+struct Failing;
+
+impl Migration for Failing {
+    fn up(&self, _db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err("no vehicles to backfill".into())
+    }
+
+    fn down(&self, _db: &mut dyn DatabaseConnection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn rust_migrations_run_in_order_with_sql_migrations() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY, name VARCHAR(255));\n\
+         INSERT INTO vehicle (name) VALUES ('Red Car'), ('Blue Van');",
+        "DROP TABLE vehicle;",
+    );
+    write_migration(
+        &migrations,
+        "20221015155759_add_owners_table",
+        "CREATE TABLE owner (id INTEGER PRIMARY KEY);",
+        "DROP TABLE owner;",
+    );
+
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+    let migrator =
+        Migrator::new(&migrations).register("20221014200000_backfill_slugs", BackfillSlugs);
+
+    let plan = migrator.plan(&mut db).unwrap();
+    assert_eq!(plan[1].name, "20221014200000_backfill_slugs");
+    assert_eq!(plan[1].sql, None);
+
+    migrator.run(&mut db).unwrap();
+    assert_eq!(
+        migration_names(&mut db),
+        vec![
+            "20221014195913_add_vehicles_table",
+            "20221014200000_backfill_slugs",
+            "20221015155759_add_owners_table"
+        ]
+    );
+    assert_eq!(
+        db.query("SELECT slug FROM vehicle ORDER BY id;").unwrap(),
+        vec![
+            vec![Some("red-car".to_string())],
+            vec![Some("blue-van".to_string())]
+        ]
+    );
+
+    let statuses = migrator.status(&mut db).unwrap();
+    assert!(statuses
+        .iter()
+        .all(|status| matches!(status.state, MigrationState::Applied(_))));
+    assert!(!statuses[1].transactional);
+    migrator.verify(&mut db).unwrap();
+
+    migrator
+        .rollback(&mut db, RollbackTarget::Steps(2))
+        .unwrap();
+    assert_eq!(
+        migration_names(&mut db),
+        vec!["20221014195913_add_vehicles_table"]
+    );
+    assert!(db.query("SELECT slug FROM vehicle;").is_err());
+
+    // A rolled back Rust migration that is no longer registered cannot be rolled back again.
+    Migrator::new(&migrations)
+        .register("20221014200000_backfill_slugs", BackfillSlugs)
+        .run(&mut db)
+        .unwrap();
+    let result = Migrator::new(&migrations).rollback(&mut db, RollbackTarget::Steps(2));
+    assert!(matches!(
+        result,
+        Err(MigrationError::MissingMigration(name)) if name == "20221014200000_backfill_slugs"
+    ));
+
+    let result = Migrator::new(&migrations)
+        .register("20221016000000_failing", Failing)
+        .run(&mut db);
+    assert!(matches!(
+        result,
+        Err(MigrationError::MigrationFailed { name, .. }) if name == "20221016000000_failing"
+    ));
+    assert_eq!(migration_names(&mut db).len(), 3);
+}
+
+static EMBEDDED: EmbeddedMigrations = embed_migrations!("../../test/migrations");
+
+#[test]
+fn embedded_migrations_run_without_the_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = sqlite_db(&dir.path().join("app.db"));
+
+    libdmt::run_migrations(&mut db, &EMBEDDED).unwrap();
+    assert_eq!(
+        migration_names(&mut db),
+        vec![
+            "20221014195913_add_vehicles_table",
+            "20221015155759_add_another_test_table"
+        ]
+    );
+    db.execute_sql("INSERT INTO another_test (id, name) VALUES (1, 'embedded');")
+        .unwrap();
+
+    // The embedded SQL has the same checksums as the files it was read from.
+    libdmt::verify_migrations(&mut db, "../../test/migrations").unwrap();
+
+    libdmt::rollback_migrations(&mut db, &EMBEDDED, RollbackTarget::All).unwrap();
+    assert!(migration_names(&mut db).is_empty());
+}
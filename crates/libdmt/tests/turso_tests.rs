@@ -0,0 +1,92 @@
+use std::str::FromStr;
+
+use libdmt::{DatabaseConnection, DmtConfig, MigrationDatabase, RollbackTarget};
+
+mod common;
+
+use common::write_migration;
+
+fn turso_db(url: &str) -> MigrationDatabase {
+    let config = DmtConfig::from_str(&format!(
+        r#"
+[connection]
+database = "turso"
+
+[connection.turso]
+url = "{url}"
+token = ""
+"#
+    ))
+    .unwrap();
+
+    MigrationDatabase::try_from(&config).unwrap()
+}
+
+#[test]
+fn memory_database_records_migrations() {
+    let mut db = turso_db(":memory:");
+
+    assert!(!db.migration_table_exists().unwrap());
+    db.create_migrations_table().unwrap();
+    assert!(db.migration_table_exists().unwrap());
+
+    let time = chrono::Utc::now().naive_utc();
+    let created = db
+        .create_migration("20221014195913_add_vehicles_table", time, Some("abc123"))
+        .unwrap();
+    assert_eq!(created.name, "20221014195913_add_vehicles_table");
+    assert_eq!(created.time, time);
+    assert_eq!(created.checksum.as_deref(), Some("abc123"));
+
+    let migrations = db.get_migrations().unwrap();
+    assert_eq!(migrations.len(), 1);
+    assert_eq!(migrations[0].id, created.id);
+
+    db.remove_migration_by_name("20221014195913_add_vehicles_table")
+        .unwrap();
+    assert!(db.get_migrations().unwrap().is_empty());
+}
+
+#[test]
+fn failed_sql_is_rolled_back() {
+    let mut db = turso_db(":memory:");
+
+    let result = db.execute_sql("CREATE TABLE vehicle (id INTEGER); SELECT * FROM missing;");
+    assert!(result.is_err());
+
+    db.execute_sql("CREATE TABLE vehicle (id INTEGER);")
+        .unwrap();
+}
+
+#[test]
+fn file_database_runs_and_rolls_back_migrations() {
+    let dir = tempfile::tempdir().unwrap();
+    let migrations = dir.path().join("migrations");
+    write_migration(
+        &migrations,
+        "20221014195913_add_vehicles_table",
+        "CREATE TABLE vehicle (id INTEGER PRIMARY KEY);",
+        "DROP TABLE vehicle;",
+    );
+
+    let url = format!("file:{}", dir.path().join("dmt.db").display());
+
+    let mut db = turso_db(&url);
+    libdmt::run_migrations(&mut db, &migrations).unwrap();
+    drop(db);
+
+    let mut db = turso_db(&url);
+    let ran: Vec<String> = db
+        .get_migrations()
+        .unwrap()
+        .into_iter()
+        .map(|migration| migration.name)
+        .collect();
+    assert_eq!(ran, vec!["20221014195913_add_vehicles_table"]);
+    db.execute_sql("INSERT INTO vehicle (id) VALUES (1);")
+        .unwrap();
+
+    libdmt::rollback_migrations(&mut db, &migrations, RollbackTarget::default()).unwrap();
+    assert!(db.get_migrations().unwrap().is_empty());
+    assert!(db.execute_sql("SELECT * FROM vehicle;").is_err());
+}
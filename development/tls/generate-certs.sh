@@ -0,0 +1,24 @@
+#!/bin/sh
+# Generates a self-signed CA, a certificate for a Postgres server on localhost signed by it, and a
+# client certificate for the `postgres` user, for trying out TLS connections against the `db-tls`
+# service of docker-compose.yml.
+set -e
+
+cd "$(dirname "$0")"
+
+openssl req -x509 -new -nodes -newkey rsa:2048 -days 365 \
+    -subj "/CN=dmt development CA" -keyout ca.key -out ca.crt
+
+openssl req -new -nodes -newkey rsa:2048 -subj "/CN=localhost" \
+    -keyout server.key -out server.csr
+printf "subjectAltName=DNS:localhost,IP:127.0.0.1\n" > server.ext
+openssl x509 -req -in server.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days 365 \
+    -extfile server.ext -out server.crt
+
+openssl req -new -nodes -newkey rsa:2048 -subj "/CN=postgres" \
+    -keyout client.key -out client.csr
+openssl x509 -req -in client.csr -CA ca.crt -CAkey ca.key -CAcreateserial -days 365 \
+    -out client.crt
+
+rm -f server.csr server.ext client.csr ca.srl
+chmod 600 ./*.key